@@ -0,0 +1,42 @@
+use std::path::{Path, PathBuf};
+
+/// Expose the autoschematic-core version this connector is built against as
+/// `AUTOSCHEMATIC_CORE_VERSION`, read from the lockfile that cargo resolved it in.
+fn main() {
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let lockfile = find_lockfile(&manifest_dir)
+        .unwrap_or_else(|| panic!("No Cargo.lock for {}", manifest_dir.display()));
+    println!("cargo:rerun-if-changed={}", lockfile.display());
+
+    let lock = std::fs::read_to_string(&lockfile).unwrap();
+    let versions: Vec<&str> = lock
+        .split("[[package]]")
+        .filter(|package| package.contains("\nname = \"autoschematic-core\"\n"))
+        .filter_map(|package| {
+            package
+                .lines()
+                .find_map(|line| line.strip_prefix("version = "))
+                .map(|version| version.trim_matches('"'))
+        })
+        .collect();
+
+    match versions.as_slice() {
+        [version] => println!("cargo:rustc-env=AUTOSCHEMATIC_CORE_VERSION={version}"),
+        [] => panic!("{} has no autoschematic-core package", lockfile.display()),
+        _ => panic!(
+            "{} has several autoschematic-core versions: {}",
+            lockfile.display(),
+            versions.join(", ")
+        ),
+    }
+}
+
+/// The workspace's lockfile, or the package's own outside a workspace.
+fn find_lockfile(manifest_dir: &Path) -> Option<PathBuf> {
+    let workspace = manifest_dir.ancestors().skip(1).find(|dir| {
+        std::fs::read_to_string(dir.join("Cargo.toml"))
+            .is_ok_and(|manifest| manifest.contains("[workspace]"))
+    });
+    let lockfile = workspace.unwrap_or(manifest_dir).join("Cargo.lock");
+    lockfile.is_file().then_some(lockfile)
+}
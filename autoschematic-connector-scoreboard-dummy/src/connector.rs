@@ -18,7 +18,12 @@ use autoschematic_core::{
 };
 
 use anyhow::Context;
-use autoschematic_verification_core::tx::Transaction;
+use autoschematic_verification_core::{
+    metadata::Metadata,
+    param::Param,
+    tx::{Outcome, Timer, Transaction},
+    writer::TxWriter,
+};
 use rand::{Rng, SeedableRng};
 use redb::Database;
 
@@ -31,7 +36,47 @@ use crate::{
 pub struct ScoreboardConnector {
     prefix: PathBuf,
     rng: Mutex<rand_chacha::ChaCha8Rng>,
-    db: redb::Database,
+    db: Arc<redb::Database>,
+    writer: TxWriter,
+    metadata: Metadata,
+}
+
+impl ScoreboardConnector {
+    /// Run `call`, and queue `tx` for writing along with its response, outcome and timing. A
+    /// transaction that can't be queued is logged rather than failing the call, so that the
+    /// connector behaves the same with or without the scoreboard.
+    ///
+    /// The response is recorded as the connector returns it, before it crosses the bridge, so
+    /// comparing runs checks what the connector answered; it doesn't check that the bridge
+    /// delivered the answer intact.
+    fn recorded<T: std::fmt::Debug>(
+        &self,
+        tx: Transaction,
+        call: impl FnOnce() -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let timer = Timer::start();
+        let response = call();
+        self.record(tx, timer, &response);
+        response
+    }
+
+    fn record<T: std::fmt::Debug>(
+        &self,
+        tx: Transaction,
+        timer: Timer,
+        response: &anyhow::Result<T>,
+    ) {
+        let kind = tx.kind.clone();
+        let result = self.writer.send(Transaction {
+            response: response.as_ref().ok().map(|r| format!("{r:#?}")),
+            outcome: Some(Outcome::of(response)),
+            timing: Some(timer.stop()),
+            ..tx
+        });
+        if let Err(e) = result {
+            tracing::error!("Failed to record a {} transaction: {:#}", kind, e);
+        }
+    }
 }
 
 #[async_trait]
@@ -44,86 +89,114 @@ impl Connector for ScoreboardConnector {
     where
         Self: Sized,
     {
-        let db = Database::create(prefix.join("scoreboard.redb"))?;
-
         let seed = std::env::var("SEED").unwrap_or(String::from("1"));
 
+        let metadata = Metadata::current(name, prefix)
+            .with_version("autoschematic-core", env!("AUTOSCHEMATIC_CORE_VERSION"))
+            .with_version(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+        // redb blocks on disk, so the store is opened off the executor.
+        let path = prefix.join("scoreboard.redb");
+        let (db, metadata) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+            let db = Database::create(&path).context(format!("open db {}", path.display()))?;
+            metadata.write(&db)?;
+            Ok((Arc::new(db), metadata))
+        })
+        .await??;
+
         Ok(Arc::new(ScoreboardConnector {
             prefix: prefix.into(),
             rng: Mutex::new(rand_chacha::ChaCha8Rng::seed_from_u64(str::parse(&seed)?)),
+            writer: TxWriter::new(db.clone())?,
             db,
+            metadata,
         }))
     }
 
     async fn init(&self) -> anyhow::Result<()> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("init"),
             params: Vec::new(),
-        }
-        .write(&self.db)?;
-        Ok(())
+            ..Default::default()
+        };
+
+        let timer = Timer::start();
+
+        // Already written by `new`, unless the table was cleared since; `write` skips duplicates.
+        let (metadata, db) = (self.metadata.clone(), self.db.clone());
+        let response: anyhow::Result<()> = tokio::task::spawn_blocking(move || metadata.write(&db))
+            .await
+            .context("writing metadata")
+            .and_then(|written| written);
+
+        self.record(tx, timer, &response);
+        response
     }
 
     async fn filter(&self, addr: &Path) -> Result<FilterResponse, anyhow::Error> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("filter"),
-            params: vec![addr.to_string_lossy().to_string()],
-        }
-        .write(&self.db)?;
+            params: vec![Param::path(addr)],
+            ..Default::default()
+        };
 
-        match ScoreboardAddress::from_path(addr) {
+        self.recorded(tx, || match ScoreboardAddress::from_path(addr) {
             Ok(ScoreboardAddress::Resource {}) => Ok(FilterResponse::Resource),
             Ok(ScoreboardAddress::Bundle {}) => Ok(FilterResponse::Bundle),
             Ok(ScoreboardAddress::Task(_)) => Ok(FilterResponse::Task),
             _ => Ok(FilterResponse::None),
-        }
+        })
     }
 
     async fn list(&self, subpath: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("list"),
-            params: vec![subpath.to_string_lossy().to_string()],
-        }
-        .write(&self.db)?;
-
-        Ok(vec![
-            // ScoreboardAddress::Bundle {}.to_path_buf(),
-            ScoreboardAddress::Resource {}.to_path_buf(),
-        ])
+            params: vec![Param::path(subpath)],
+            ..Default::default()
+        };
+
+        self.recorded(tx, || {
+            Ok(vec![
+                // ScoreboardAddress::Bundle {}.to_path_buf(),
+                ScoreboardAddress::Resource {}.to_path_buf(),
+            ])
+        })
     }
 
     async fn get(&self, addr: &Path) -> Result<Option<GetResourceResponse>, anyhow::Error> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("get"),
-            params: vec![addr.to_string_lossy().to_string()],
-        }
-        .write(&self.db)?;
+            params: vec![Param::path(addr)],
+            ..Default::default()
+        };
 
-        let _addr = ScoreboardAddress::from_path(addr)?;
+        self.recorded(tx, || {
+            let _addr = ScoreboardAddress::from_path(addr)?;
 
-        get_resource_response!(ScoreboardState {
-            random_int: self.rng.lock().unwrap().random()
+            get_resource_response!(ScoreboardState {
+                random_int: self.rng.lock().unwrap().random()
+            })
         })
     }
 
     async fn addr_virt_to_phy(&self, addr: &Path) -> Result<VirtToPhyResponse, anyhow::Error> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("addr_virt_to_phy"),
-            params: vec![addr.to_string_lossy().to_string()],
-        }
-        .write(&self.db)?;
+            params: vec![Param::path(addr)],
+            ..Default::default()
+        };
 
-        Ok(VirtToPhyResponse::Null(addr.into()))
+        self.recorded(tx, || Ok(VirtToPhyResponse::Null(addr.into())))
     }
 
     async fn addr_phy_to_virt(&self, addr: &Path) -> anyhow::Result<Option<PathBuf>> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("addr_phy_to_virt"),
-            params: vec![addr.to_string_lossy().to_string()],
-        }
-        .write(&self.db)?;
+            params: vec![Param::path(addr)],
+            ..Default::default()
+        };
 
-        Ok(Some(addr.into()))
+        self.recorded(tx, || Ok(Some(addr.into())))
     }
 
     async fn unbundle(
@@ -131,30 +204,29 @@ impl Connector for ScoreboardConnector {
         addr: &Path,
         bundle: &[u8],
     ) -> anyhow::Result<Vec<UnbundleResponseElement>> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("unbundle"),
-            params: vec![
-                addr.to_string_lossy().to_string(),
-                str::from_utf8(bundle)?.to_string(),
-            ],
-        }
-        .write(&self.db)?;
-
-        let addr = ScoreboardAddress::from_path(addr)?;
-
-        match addr {
-            ScoreboardAddress::Bundle {} => Ok(vec![
-                UnbundleResponseElement {
-                    addr: PathBuf::from("scoreboard/bundle.1.out"),
-                    contents: "testbench generic output".into(),
-                },
-                UnbundleResponseElement {
-                    addr: PathBuf::from("scoreboard/bundle.2.out"),
-                    contents: "testbench generic output".into(),
-                },
-            ]),
-            _ => Ok(vec![]),
-        }
+            params: vec![Param::path(addr), Param::utf8(bundle)],
+            ..Default::default()
+        };
+
+        self.recorded(tx, || {
+            let addr = ScoreboardAddress::from_path(addr)?;
+
+            match addr {
+                ScoreboardAddress::Bundle {} => Ok(vec![
+                    UnbundleResponseElement {
+                        addr: PathBuf::from("scoreboard/bundle.1.out"),
+                        contents: "testbench generic output".into(),
+                    },
+                    UnbundleResponseElement {
+                        addr: PathBuf::from("scoreboard/bundle.2.out"),
+                        contents: "testbench generic output".into(),
+                    },
+                ]),
+                _ => Ok(vec![]),
+            }
+        })
     }
 
     async fn plan(
@@ -163,65 +235,68 @@ impl Connector for ScoreboardConnector {
         current: Option<Vec<u8>>,
         desired: Option<Vec<u8>>,
     ) -> Result<Vec<PlanResponseElement>, anyhow::Error> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("plan"),
             params: vec![
-                addr.to_string_lossy().to_string(),
-                String::from_utf8(current.clone().unwrap_or_default())?,
-                String::from_utf8(desired.clone().unwrap_or_default())?,
+                Param::path(addr),
+                Param::optional(current.as_deref().map(Param::ron)),
+                Param::optional(desired.as_deref().map(Param::ron)),
             ],
-        }
-        .write(&self.db)?;
-
-        let addr = ScoreboardAddress::from_path(addr)?;
-
-        match (current, desired) {
-            (Some(current), Some(desired)) => {
-                let _current = ScoreboardState::from_bytes(&addr, &current)?;
-                let desired = ScoreboardState::from_bytes(&addr, &desired)?;
-                let i = desired.random_int;
-                Ok(vec![connector_op!(
-                    ScoreboardConnectorOp::SetState(desired),
-                    format!("Set the state to {}", i)
-                )])
+            ..Default::default()
+        };
+
+        self.recorded(tx, || {
+            let addr = ScoreboardAddress::from_path(addr)?;
+
+            match (current, desired) {
+                (Some(current), Some(desired)) => {
+                    let _current = ScoreboardState::from_bytes(&addr, &current)?;
+                    let desired = ScoreboardState::from_bytes(&addr, &desired)?;
+                    let i = desired.random_int;
+                    Ok(vec![connector_op!(
+                        ScoreboardConnectorOp::SetState(desired),
+                        format!("Set the state to {}", i)
+                    )])
+                }
+                other => Ok(Vec::new()),
             }
-            other => Ok(Vec::new()),
-        }
+        })
     }
 
     async fn op_exec(&self, addr: &Path, op: &str) -> Result<OpExecResponse, anyhow::Error> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("op_exec"),
-            params: vec![addr.to_string_lossy().to_string(), op.to_string()],
-        }
-        .write(&self.db)?;
-
-        let _addr = ScoreboardAddress::from_path(addr)?;
-        let op = ScoreboardConnectorOp::from_str(op)?;
-
-        match op {
-            ScoreboardConnectorOp::SetState(light_state) => {
-                return Ok(OpExecResponse {
-                    outputs: None,
-                    friendly_message: Some("Set the state to the desired setting.".into()),
-                });
+            params: vec![Param::path(addr), Param::Ron(op.to_string())],
+            ..Default::default()
+        };
+
+        self.recorded(tx, || {
+            let _addr = ScoreboardAddress::from_path(addr)?;
+            let op = ScoreboardConnectorOp::from_str(op)?;
+
+            match op {
+                ScoreboardConnectorOp::SetState(light_state) => {
+                    return Ok(OpExecResponse {
+                        outputs: None,
+                        friendly_message: Some("Set the state to the desired setting.".into()),
+                    });
+                }
             }
-        }
+        })
     }
 
     async fn eq(&self, addr: &Path, a: &[u8], b: &[u8]) -> Result<bool, anyhow::Error> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("eq"),
-            params: vec![
-                addr.to_string_lossy().to_string(),
-                String::from_utf8(a.to_vec())?,
-                String::from_utf8(b.to_vec())?,
-            ],
-        }
-        .write(&self.db)?;
-        let _addr = ScoreboardAddress::from_path(addr)?;
+            params: vec![Param::path(addr), Param::ron(a), Param::ron(b)],
+            ..Default::default()
+        };
+
+        self.recorded(tx, || {
+            let _addr = ScoreboardAddress::from_path(addr)?;
 
-        return ron_check_eq::<ScoreboardState>(a, b);
+            return ron_check_eq::<ScoreboardState>(a, b);
+        })
     }
 
     async fn diag(
@@ -229,17 +304,17 @@ impl Connector for ScoreboardConnector {
         addr: &Path,
         a: &[u8],
     ) -> Result<Option<DiagnosticResponse>, anyhow::Error> {
-        Transaction {
+        let tx = Transaction {
             kind: String::from("diag"),
-            params: vec![
-                addr.to_string_lossy().to_string(),
-                String::from_utf8(a.to_vec())?,
-            ],
-        }
-        .write(&self.db)?;
-        let _addr = ScoreboardAddress::from_path(addr)?;
+            params: vec![Param::path(addr), Param::ron(a)],
+            ..Default::default()
+        };
+
+        self.recorded(tx, || {
+            let _addr = ScoreboardAddress::from_path(addr)?;
 
-        return ron_check_syntax::<ScoreboardState>(a);
+            return ron_check_syntax::<ScoreboardState>(a);
+        })
     }
 
     async fn task_exec(
@@ -256,20 +331,20 @@ impl Connector for ScoreboardConnector {
         tracing::warn!(
             "task_exec({}, {})",
             addr.display(),
-            str::from_utf8(&body).unwrap()
+            String::from_utf8_lossy(&body)
         );
-        Transaction {
+        let tx = Transaction {
             kind: String::from("task_exec"),
             params: vec![
-                addr.to_string_lossy().to_string(),
-                String::from_utf8(body)?,
-                String::from_utf8(arg.clone().unwrap_or_default())?,
-                String::from_utf8(state.clone().unwrap_or_default())?,
+                Param::path(addr),
+                Param::ron(&body),
+                Param::optional(arg.as_deref().map(Param::utf8)),
+                Param::optional(state.as_deref().map(Param::utf8)),
             ],
-        }
-        .write(&self.db)?;
+            ..Default::default()
+        };
 
-        match ScoreboardAddress::from_path(addr)? {
+        self.recorded(tx, || match ScoreboardAddress::from_path(addr)? {
             ScoreboardAddress::Task(ScoreboardTaskType::CountDown) => {
                 let arg = arg.map(|s| str::parse::<usize>(str::from_utf8(&s).unwrap()).unwrap());
                 let state =
@@ -290,6 +365,6 @@ impl Connector for ScoreboardConnector {
                 })
             }
             _ => Ok(TaskExecResponse::default()),
-        }
+        })
     }
 }
@@ -1,13 +1,15 @@
 use autoschematic_core::{grpc_bridge::grpc_connector_main, tarpc_bridge::tarpc_connector_main};
+use autoschematic_verification_core::writer;
 use connector::ScoreboardConnector;
+#[cfg(unix)]
+use tokio::signal::unix::{SignalKind, signal};
 
 pub mod addr;
 pub mod connector;
 pub mod op;
 pub mod resource;
 
-#[tokio::main]
-pub async fn main() -> anyhow::Result<()> {
+async fn connector_main() -> anyhow::Result<()> {
     let protocol = std::env::var("PROTOCOL");
     if protocol == Ok(String::from("GRPC")) {
         grpc_connector_main::<ScoreboardConnector>().await?;
@@ -16,3 +18,30 @@ pub async fn main() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+/// Resolves on Ctrl-C, or on SIGTERM where there is one.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn main() -> anyhow::Result<()> {
+    let result = tokio::select! {
+        result = connector_main() => result,
+        result = shutdown_signal() => result,
+    };
+
+    // Transactions are written in the background; make sure none are lost on the way out.
+    writer::close_all()?;
+    result
+}
@@ -1,8 +1,12 @@
-use clap::{arg, command, Parser, Subcommand};
+use autoschematic_verification_core::export;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(name = "autoschematic-testbench")]
 pub struct AutoschematicTestBenchCommand {
+    /// A RON list of normalizers to apply before each sequence's own.
+    #[arg(long, global = true)]
+    pub normalizers: Option<String>,
     #[command(subcommand)]
     pub command: AutoschematicTestBenchSubcommand,
 }
@@ -18,10 +22,153 @@ pub enum AutoschematicTestBenchSubcommand {
     Run {
         #[arg(short, long)]
         sequence: String,
+        /// Name of the new run in each tx_store. Defaults to one based on the current time.
+        #[arg(long)]
+        run_id: Option<String>,
+        /// Also report per-kind call latency of every tx_store.
+        #[arg(long)]
+        latency: bool,
+        #[command(flatten)]
+        output: OutputArgs,
     },
     /// Run a test sequence and save its transactions back to that same sequence file.
     Record {
         #[arg(short, long)]
         sequence: String,
+        /// Name of the new run in each tx_store. Defaults to one based on the current time.
+        #[arg(long)]
+        run_id: Option<String>,
+        #[command(flatten)]
+        output: OutputArgs,
     },
+    /// Verify a previous run of every tx_store against a sequence, without running its commands.
+    Check {
+        #[arg(short, long)]
+        sequence: String,
+        /// The run to verify. Defaults to the latest run.
+        #[arg(long)]
+        run: Option<String>,
+        /// Also report per-kind call latency of every tx_store.
+        #[arg(long)]
+        latency: bool,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Compare two runs of a tx_store, or of two tx_stores, the left one serving as the
+    /// expectation.
+    Compare {
+        #[arg(long)]
+        store: String,
+        #[arg(long)]
+        left: String,
+        /// The tx_store of the right run. Defaults to `--store`.
+        #[arg(long)]
+        right_store: Option<String>,
+        /// Defaults to the latest run.
+        #[arg(long)]
+        right: Option<String>,
+        /// Compare with this sequence's options rather than the defaults.
+        #[arg(short, long)]
+        sequence: Option<String>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Upgrade sequence files and their tx_stores, or lone tx_stores, to the current schema
+    /// version in place.
+    Migrate {
+        #[arg(short, long)]
+        sequence: Vec<String>,
+        #[arg(long)]
+        store: Vec<String>,
+    },
+    /// Write a run of a tx_store as text, one transaction per line.
+    Export {
+        #[arg(long)]
+        store: String,
+        /// Defaults to the latest run.
+        #[arg(long)]
+        run: Option<String>,
+        /// Defaults to the extension of `--out`, or JSONL.
+        #[arg(long, value_enum)]
+        format: Option<StoreFormat>,
+        /// Include timestamps and timing, which differ between otherwise equal stores.
+        #[arg(long)]
+        timing: bool,
+        /// Defaults to stdout.
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Read an export back into a new run of a tx_store, creating the store if needed.
+    Import {
+        input: String,
+        #[arg(long)]
+        store: String,
+        /// Name of the new run. Defaults to one based on the current time.
+        #[arg(long)]
+        run_id: Option<String>,
+        /// Defaults to the extension of the input, or JSONL.
+        #[arg(long, value_enum)]
+        format: Option<StoreFormat>,
+    },
+    /// Print what the commands of a run printed.
+    Output {
+        #[arg(long)]
+        store: String,
+        /// Defaults to the latest run.
+        #[arg(long)]
+        run: Option<String>,
+    },
+    /// List the runs in a tx_store.
+    Runs {
+        #[arg(long)]
+        store: String,
+    },
+    /// Delete all but the latest runs of a tx_store.
+    Prune {
+        #[arg(long)]
+        store: String,
+        /// Number of runs to keep.
+        #[arg(long)]
+        keep: usize,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// Width of text output. Defaults to $COLUMNS, or 120.
+    #[arg(long)]
+    pub width: Option<usize>,
+    /// Number of matching transactions to show around each mismatch in text output.
+    #[arg(long, default_value_t = 0)]
+    pub context: usize,
+    /// Show every transaction in text output, not only mismatches.
+    #[arg(long)]
+    pub all: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreFormat {
+    /// One JSON object per line.
+    Jsonl,
+    /// One RON struct per line.
+    Ron,
+}
+
+impl From<StoreFormat> for export::Format {
+    fn from(format: StoreFormat) -> Self {
+        match format {
+            StoreFormat::Jsonl => export::Format::Jsonl,
+            StoreFormat::Ron => export::Format::Ron,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable reports on stderr.
+    Text,
+    /// Reports as a JSON array on stdout.
+    Json,
 }
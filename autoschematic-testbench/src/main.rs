@@ -1,12 +1,87 @@
 pub mod cmd;
 
-use anyhow::Context;
-use autoschematic_verification_core::sequence::Sequence;
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, bail};
+use autoschematic_verification_core::{
+    crosscheck::{self, CompareOptions},
+    export::{self, Format},
+    normalize::Normalizer,
+    output::StepOutput,
+    render::{self, RenderOptions},
+    report::Report,
+    run, schema,
+    sequence::Sequence,
+    tx::Transaction,
+};
 use clap::Parser;
 use ron::ser::PrettyConfig;
 use tracing_subscriber::EnvFilter;
 
-use crate::cmd::AutoschematicTestBenchCommand;
+use crate::cmd::{AutoschematicTestBenchCommand, OutputArgs, OutputFormat};
+
+/// Print the reports in the requested format, and fail if any of them found a mismatch.
+fn emit(reports: &[Report], output: &OutputArgs) -> anyhow::Result<()> {
+    match output.format {
+        OutputFormat::Text => {
+            let options = RenderOptions {
+                width: output.width.unwrap_or_else(render::terminal_width),
+                context: output.context,
+                all: output.all,
+            };
+            for report in reports {
+                eprint!("{}", render::render(report, &options));
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(reports)?),
+    }
+
+    if reports.iter().any(|r| !r.is_ok()) {
+        bail!("Mismatch detected!");
+    }
+
+    Ok(())
+}
+
+/// Read a sequence file, adding the normalizers in the `normalizers` file if given.
+fn load_sequence(path: &str, normalizers: Option<&str>) -> anyhow::Result<Sequence> {
+    let mut sequence: Sequence =
+        ron::from_str(&std::fs::read_to_string(path).context(format!("reading {}", path))?)
+            .context(format!("parsing {}", path))?;
+    if let Some(normalizers) = normalizers {
+        sequence.add_normalizers(load_normalizers(normalizers)?)?;
+    }
+    Ok(sequence)
+}
+
+fn load_normalizers(path: &str) -> anyhow::Result<Vec<Normalizer>> {
+    ron::from_str(&std::fs::read_to_string(path).context(format!("reading {}", path))?)
+        .context(format!("parsing {}", path))
+}
+
+/// The options to compare with outside of a sequence run: the sequence's if given, with the
+/// `normalizers` file's normalizers added, as `check` would use.
+fn compare_options(
+    sequence: Option<&str>,
+    normalizers: Option<&str>,
+) -> anyhow::Result<CompareOptions> {
+    match sequence {
+        Some(sequence) => Ok(load_sequence(sequence, normalizers)?
+            .compare_options()
+            .clone()),
+        None => {
+            let mut options = CompareOptions::default();
+            if let Some(normalizers) = normalizers {
+                options.normalize = options.normalize.after(load_normalizers(normalizers)?)?;
+            }
+            Ok(options)
+        }
+    }
+}
 
 fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt()
@@ -26,25 +101,179 @@ fn main() -> anyhow::Result<()> {
                 ron::ser::to_string_pretty(&Sequence::default(), PrettyConfig::default())?,
             )?;
         }
-        cmd::AutoschematicTestBenchSubcommand::Run { sequence } => {
-            let paths = std::fs::read_dir("./").unwrap();
-
-            for path in paths {
-                println!("Name: {}", path.unwrap().path().display())
-            }
-            let sequence: Sequence = ron::from_str(
-                &std::fs::read_to_string(&sequence).context(format!("reading {}", sequence))?,
-            )?;
-            sequence.run()?;
+        cmd::AutoschematicTestBenchSubcommand::Run {
+            sequence,
+            run_id,
+            latency,
+            output,
+        } => {
+            let sequence = load_sequence(&sequence, cmd.normalizers.as_deref())?;
+            let run_id = run_id.unwrap_or_else(run::new_id);
+            emit(&sequence.run(&run_id, latency)?, &output)?;
         }
-        cmd::AutoschematicTestBenchSubcommand::Record { sequence } => {
-            let mut out_sequence: Sequence = ron::from_str(&std::fs::read_to_string(&sequence)?)?;
-            out_sequence.record()?;
+        cmd::AutoschematicTestBenchSubcommand::Record {
+            sequence,
+            run_id,
+            output,
+        } => {
+            let mut out_sequence = load_sequence(&sequence, cmd.normalizers.as_deref())?;
+            let run_id = run_id.unwrap_or_else(run::new_id);
+            emit(&out_sequence.record(&run_id)?, &output)?;
             std::fs::write(
                 sequence,
                 ron::ser::to_string_pretty(&out_sequence, PrettyConfig::default())?,
             )?;
         }
+        cmd::AutoschematicTestBenchSubcommand::Check {
+            sequence,
+            run,
+            latency,
+            output,
+        } => {
+            let sequence = load_sequence(&sequence, cmd.normalizers.as_deref())?;
+            emit(&sequence.check(run.as_deref(), latency)?, &output)?;
+        }
+        cmd::AutoschematicTestBenchSubcommand::Compare {
+            store,
+            left,
+            right_store,
+            right,
+            sequence,
+            output,
+        } => {
+            let options = compare_options(sequence.as_deref(), cmd.normalizers.as_deref())?;
+            let db = redb::Database::open(&store).context(format!("open db {}", store))?;
+            // A store can only be opened once, so comparing it to itself reuses it.
+            let right_store = right_store.filter(|right_store| *right_store != store);
+            let right_db = match &right_store {
+                Some(path) => {
+                    Some(redb::Database::open(path).context(format!("open db {}", path))?)
+                }
+                None => None,
+            };
+            let (right_name, right_db) = match (&right_store, &right_db) {
+                (Some(path), Some(right_db)) => (path.as_str(), right_db),
+                _ => (store.as_str(), &db),
+            };
+            let Some(right) = run::resolve(right_db, right.as_deref())? else {
+                bail!("No runs in {}", right_name);
+            };
+            let report = crosscheck::compare_store_runs_report(
+                (&store, &db, &left),
+                (right_name, right_db, &right),
+                &options,
+            )?;
+            emit(&[Report::Comparison(report)], &output)?;
+        }
+        cmd::AutoschematicTestBenchSubcommand::Migrate { sequence, store } => {
+            for path in sequence {
+                let mut sequence: Sequence = ron::from_str(
+                    &std::fs::read_to_string(&path).context(format!("reading {}", path))?,
+                )?;
+                sequence.migrate()?;
+                std::fs::write(
+                    &path,
+                    ron::ser::to_string_pretty(&sequence, PrettyConfig::default())?,
+                )?;
+                eprintln!("Migrated {}", path);
+            }
+            for path in store {
+                let db = redb::Database::open(&path).context(format!("open db {}", path))?;
+                for version in schema::migrate_store(&db)? {
+                    eprintln!("Migrated {} to schema version {}", path, version);
+                }
+            }
+        }
+        cmd::AutoschematicTestBenchSubcommand::Export {
+            store,
+            run,
+            format,
+            timing,
+            out,
+        } => {
+            let format = format
+                .map(Format::from)
+                .or_else(|| {
+                    out.as_deref()
+                        .and_then(|out| Format::from_path(Path::new(out)))
+                })
+                .unwrap_or(Format::Jsonl);
+            let db = redb::Database::open(&store).context(format!("open db {}", store))?;
+            let txs = Transaction::read_run(&db, run.as_deref())?;
+
+            let mut buf = Vec::new();
+            export::export(&txs, format, timing, &mut buf)?;
+            match out {
+                Some(out) => std::fs::write(&out, buf).context(format!("writing {}", out))?,
+                None => std::io::stdout().write_all(&buf)?,
+            }
+        }
+        cmd::AutoschematicTestBenchSubcommand::Import {
+            input,
+            store,
+            run_id,
+            format,
+        } => {
+            let format = format
+                .map(Format::from)
+                .or_else(|| Format::from_path(Path::new(&input)))
+                .unwrap_or(Format::Jsonl);
+            let txs = export::import(
+                &std::fs::read_to_string(&input).context(format!("reading {}", input))?,
+                format,
+            )
+            .context(format!("parsing {}", input))?;
+
+            let db = redb::Database::create(&store).context(format!("create db {}", store))?;
+            let run_id = run_id.unwrap_or_else(run::new_id);
+            run::begin(&db, &run_id)?;
+            Transaction::write_all(&db, &txs)?;
+            eprintln!(
+                "Imported {} transaction(s) into run {} of {}",
+                txs.len(),
+                run_id,
+                store
+            );
+        }
+        cmd::AutoschematicTestBenchSubcommand::Output { store, run } => {
+            let db = redb::Database::open(&store).context(format!("open db {}", store))?;
+            for output in StepOutput::read_run(&db, run.as_deref())? {
+                let status = match output.exit_code {
+                    _ if output.timed_out => "timed out".to_string(),
+                    Some(code) => format!("exit code {}", code),
+                    None => "killed".to_string(),
+                };
+                println!("=== step {}: {} ({})", output.step, output.command, status);
+                for (name, text) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+                    if !text.is_empty() {
+                        println!("--- {}", name);
+                        print!("{}", text);
+                        if !text.ends_with('\n') {
+                            println!();
+                        }
+                    }
+                }
+            }
+        }
+        cmd::AutoschematicTestBenchSubcommand::Runs { store } => {
+            let db = redb::Database::open(&store).context(format!("open db {}", store))?;
+            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+            for r in run::list(&db)? {
+                let age = now.saturating_sub(Duration::from_nanos(r.started));
+                println!(
+                    "{}\tstarted {}s ago\t{} transaction(s)",
+                    r.id,
+                    age.as_secs(),
+                    run::len(&db, Some(&r.id))?
+                );
+            }
+        }
+        cmd::AutoschematicTestBenchSubcommand::Prune { store, keep } => {
+            let db = redb::Database::open(&store).context(format!("open db {}", store))?;
+            for id in run::prune(&db, keep)? {
+                eprintln!("Deleted run {}", id);
+            }
+        }
     }
 
     // match cmd.command {
@@ -0,0 +1,152 @@
+/// One step of an alignment between a left and a right sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// left[left] and right[right] are equal.
+    Match { left: usize, right: usize },
+    /// left[left] was replaced by right[right].
+    Change { left: usize, right: usize },
+    /// left[left] has no counterpart on the right.
+    Missing { left: usize },
+    /// right[right] has no counterpart on the left.
+    Extra { right: usize },
+}
+
+impl Edit {
+    pub fn is_match(&self) -> bool {
+        matches!(self, Edit::Match { .. })
+    }
+}
+
+/// Align two sequences by minimum edit distance.
+///
+/// `eq(i, j)` decides whether left[i] and right[j] are identical. `similar(i, j)` decides
+/// whether two unequal elements may be reported as a single `Change` (e.g. same kind)
+/// rather than as a `Missing` + `Extra` pair.
+pub fn align(
+    left_len: usize,
+    right_len: usize,
+    eq: impl Fn(usize, usize) -> bool,
+    similar: impl Fn(usize, usize) -> bool,
+) -> Vec<Edit> {
+    let width = right_len + 1;
+    // cost[i * width + j] is the edit distance between left[i..] and right[j..].
+    let mut cost = vec![0u32; (left_len + 1) * width];
+    let mut equal = vec![false; left_len * right_len];
+
+    for i in (0..=left_len).rev() {
+        for j in (0..=right_len).rev() {
+            cost[i * width + j] = if i == left_len {
+                (right_len - j) as u32
+            } else if j == right_len {
+                (left_len - i) as u32
+            } else {
+                let is_eq = eq(i, j);
+                equal[i * right_len + j] = is_eq;
+                let diag = if is_eq {
+                    cost[(i + 1) * width + j + 1]
+                } else if similar(i, j) {
+                    cost[(i + 1) * width + j + 1] + 1
+                } else {
+                    u32::MAX
+                };
+                let missing = cost[(i + 1) * width + j] + 1;
+                let extra = cost[i * width + j + 1] + 1;
+                diag.min(missing).min(extra)
+            };
+        }
+    }
+
+    let mut edits = Vec::with_capacity(left_len.max(right_len));
+    let (mut i, mut j) = (0, 0);
+    while i < left_len || j < right_len {
+        let here = cost[i * width + j];
+        if i < left_len && j < right_len {
+            if equal[i * right_len + j] && here == cost[(i + 1) * width + j + 1] {
+                edits.push(Edit::Match { left: i, right: j });
+                i += 1;
+                j += 1;
+                continue;
+            }
+            if !equal[i * right_len + j]
+                && here == cost[(i + 1) * width + j + 1] + 1
+                && similar(i, j)
+            {
+                edits.push(Edit::Change { left: i, right: j });
+                i += 1;
+                j += 1;
+                continue;
+            }
+        }
+        if i < left_len && here == cost[(i + 1) * width + j] + 1 {
+            edits.push(Edit::Missing { left: i });
+            i += 1;
+        } else {
+            edits.push(Edit::Extra { right: j });
+            j += 1;
+        }
+    }
+
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn align_str(left: &str, right: &str) -> Vec<Edit> {
+        let (left, right): (Vec<char>, Vec<char>) =
+            (left.chars().collect(), right.chars().collect());
+        // Letters of the same case may be reported as changed into one another.
+        align(
+            left.len(),
+            right.len(),
+            |i, j| left[i] == right[j],
+            |i, j| left[i].is_uppercase() == right[j].is_uppercase(),
+        )
+    }
+
+    #[test]
+    fn empty_sides() {
+        assert_eq!(align_str("", ""), vec![]);
+        assert_eq!(
+            align_str("ab", ""),
+            vec![Edit::Missing { left: 0 }, Edit::Missing { left: 1 }]
+        );
+        assert_eq!(align_str("", "a"), vec![Edit::Extra { right: 0 }]);
+    }
+
+    #[test]
+    fn changes_only_between_similar_elements() {
+        assert_eq!(
+            align_str("abc", "axc"),
+            vec![
+                Edit::Match { left: 0, right: 0 },
+                Edit::Change { left: 1, right: 1 },
+                Edit::Match { left: 2, right: 2 },
+            ]
+        );
+        assert_eq!(
+            align_str("abc", "aXc"),
+            vec![
+                Edit::Match { left: 0, right: 0 },
+                Edit::Missing { left: 1 },
+                Edit::Extra { right: 1 },
+                Edit::Match { left: 2, right: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn prefers_matches_over_changes() {
+        // Shifting by one is two edits, changing every element three.
+        assert_eq!(
+            align_str("abc", "bcd"),
+            vec![
+                Edit::Missing { left: 0 },
+                Edit::Match { left: 1, right: 0 },
+                Edit::Match { left: 2, right: 1 },
+                Edit::Extra { right: 2 },
+            ]
+        );
+    }
+}
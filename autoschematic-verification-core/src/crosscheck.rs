@@ -1,73 +1,804 @@
+use std::{collections::BTreeMap, ops::Range};
+
 use anyhow::bail;
-use colored::Colorize;
-use redb::{ReadableDatabase, ReadableTable};
+use serde::{Deserialize, Serialize};
 
-use crate::{TABLE, tx::Transaction};
+use crate::{
+    align::{Edit, align},
+    expect::{Captures, CompiledTx, Expectation, ExpectedTx, Group},
+    normalize::Normalizers,
+    param::Param,
+    render::{self, RenderOptions},
+    report::{ComparisonReport, Entry},
+    semantic,
+    tx::Transaction,
+    txdiff,
+};
 
-pub fn compare(db1: redb::Database, db2: redb::Database, quiet: bool) -> anyhow::Result<()> {
-    let read_txn1 = db1.begin_read()?;
-    let table1 = read_txn1.open_table(TABLE)?;
+/// How transaction params are compared.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParamMode {
+    /// Params must be equal strings.
+    #[default]
+    Exact,
+    /// Params that parse as RON are compared as RON values, ignoring formatting.
+    /// Params that don't parse as RON must still be equal strings.
+    Ron,
+}
 
-    let read_txn2 = db2.begin_read()?;
-    let table2 = read_txn2.open_table(TABLE)?;
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CompareOptions {
+    #[serde(default)]
+    pub params: ParamMode,
+    /// Only compare transactions of these kinds. Empty means every kind.
+    #[serde(default)]
+    pub include_kinds: Vec<String>,
+    /// Never compare transactions of these kinds.
+    #[serde(default)]
+    pub ignore_kinds: Vec<String>,
+    /// For each kind listed, only compare the params at these indices, e.g. `{"filter": [0]}`.
+    #[serde(default)]
+    pub projections: BTreeMap<String, Vec<usize>>,
+    /// Rewrites applied to params, responses and errors as stores are read, and to captured
+    /// step output, e.g. to mask timestamps and temp dirs.
+    #[serde(default)]
+    pub normalize: Normalizers,
+}
 
-    let mut err = false;
-    for (a, b) in table1.iter()?.zip(table2.iter()?) {
-        let a = a?.1.value();
-        let b = b?.1.value();
+impl CompareOptions {
+    /// Whether these are the options a sequence without a `compare` section gets.
+    pub fn is_default(&self) -> bool {
+        self.params == ParamMode::Exact
+            && self.include_kinds.is_empty()
+            && self.ignore_kinds.is_empty()
+            && self.projections.is_empty()
+            && self.normalize.is_empty()
+    }
 
-        let tx1: Transaction = serde_json::from_str(&a)?;
-        let tx2: Transaction = serde_json::from_str(&b)?;
+    pub fn includes(&self, kind: &str) -> bool {
+        (self.include_kinds.is_empty() || self.include_kinds.iter().any(|k| k == kind))
+            && !self.ignore_kinds.iter().any(|k| k == kind)
+    }
 
-        if tx1 != tx2 {
-            err = true;
-            if !quiet {
-                eprintln!("{}: {:#?} {:#?}", "Diff".red(), tx1, tx2);
+    /// The params at the kind's projected indices, or all of them if it has no projection.
+    /// An index beyond the params is an error rather than skipped, so that a projection can't
+    /// silently compare less than it says.
+    fn project_params<T: Clone>(&self, kind: &str, params: Vec<T>) -> anyhow::Result<Vec<T>> {
+        match self.projections.get(kind) {
+            Some(indices) => indices
+                .iter()
+                .map(|&i| match params.get(i) {
+                    Some(param) => Ok(param.clone()),
+                    None => bail!(
+                        "Projection for {} names param {}, but a {} transaction has {} params",
+                        kind,
+                        i,
+                        kind,
+                        params.len()
+                    ),
+                })
+                .collect(),
+            None => Ok(params),
+        }
+    }
+
+    /// Filter out a transaction whose kind isn't compared, or normalize it.
+    pub fn normalized(&self, tx: Transaction) -> Option<Transaction> {
+        if !self.includes(&tx.kind) {
+            return None;
+        }
+        Some(self.normalize.apply_tx(tx))
+    }
+
+    /// Filter out a transaction whose kind isn't compared, or normalize it and project its
+    /// params.
+    pub fn project(&self, tx: Transaction) -> anyhow::Result<Option<Transaction>> {
+        let Some(tx) = self.normalized(tx) else {
+            return Ok(None);
+        };
+        let params = self.project_params(&tx.kind, tx.params)?;
+        Ok(Some(Transaction { params, ..tx }))
+    }
+
+    fn project_expected(&self, tx: &ExpectedTx) -> anyhow::Result<Option<ExpectedTx>> {
+        if !self.includes(&tx.kind) {
+            return Ok(None);
+        }
+        Ok(Some(ExpectedTx {
+            kind: tx.kind.clone(),
+            params: self.project_params(&tx.kind, tx.params.clone())?,
+            response: tx.response.clone(),
+            outcome: tx.outcome.clone(),
+        }))
+    }
+
+    /// Filter and project an expectation the same way as `project` does transactions.
+    /// Groups left without members are dropped.
+    pub fn project_expectation(
+        &self,
+        expectation: &Expectation,
+    ) -> anyhow::Result<Option<Expectation>> {
+        match expectation {
+            Expectation::Tx(tx) => Ok(self.project_expected(tx)?.map(Expectation::Tx)),
+            Expectation::Group(group) => {
+                let mut members = Vec::new();
+                for tx in group.members() {
+                    members.extend(self.project_expected(tx)?);
+                }
+                if members.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(Expectation::Group(match group {
+                    Group::Unordered(_) => Group::Unordered(members),
+                    Group::PerAddress(_) => Group::PerAddress(members),
+                })))
             }
-        } else {
-            if !quiet {
-                eprintln!("{}: {:#?}", "Same".green(), tx1);
+        }
+    }
+
+    /// Read a store's transactions, filtered and projected for comparison.
+    pub fn read(&self, db: &redb::Database) -> anyhow::Result<Vec<Transaction>> {
+        self.read_run(db, None)
+    }
+
+    /// Like `read`, but from `run` rather than the latest run if given.
+    pub fn read_run(
+        &self,
+        db: &redb::Database,
+        run: Option<&str>,
+    ) -> anyhow::Result<Vec<Transaction>> {
+        let mut txs = Vec::new();
+        for tx in Transaction::read_run(db, run)? {
+            txs.extend(self.project(tx)?);
+        }
+        Ok(txs)
+    }
+
+    /// Like `read_run`, but with every param kept, e.g. to record expectations from: those are
+    /// projected when they're compared.
+    pub fn read_run_unprojected(
+        &self,
+        db: &redb::Database,
+        run: Option<&str>,
+    ) -> anyhow::Result<Vec<Transaction>> {
+        Ok(Transaction::read_run(db, run)?
+            .into_iter()
+            .filter_map(|tx| self.normalized(tx))
+            .collect())
+    }
+
+    pub fn param_eq(&self, a: &str, b: &str) -> bool {
+        match self.params {
+            ParamMode::Exact => a == b,
+            ParamMode::Ron => semantic::ron_eq(a, b),
+        }
+    }
+
+    /// Compare two params of the same type like `param_eq` compares strings.
+    /// Params of different types are never equal.
+    pub fn value_eq(&self, a: &Param, b: &Param) -> bool {
+        match (a, b) {
+            (Param::Text(a), Param::Text(b)) | (Param::Ron(a), Param::Ron(b)) => {
+                self.param_eq(a, b)
             }
+            (Param::Optional(Some(a)), Param::Optional(Some(b))) => self.value_eq(a, b),
+            (a, b) => a == b,
         }
     }
 
-    if err {
-        bail!("Mismatch detected!")
+    pub fn tx_eq(&self, a: &Transaction, b: &Transaction) -> bool {
+        a.kind == b.kind
+            && a.params.len() == b.params.len()
+            && a.params
+                .iter()
+                .zip(&b.params)
+                .all(|(a, b)| self.value_eq(a, b))
+            && match (&a.response, &b.response) {
+                (Some(a), Some(b)) => self.param_eq(a, b),
+                (a, b) => a == b,
+            }
+            && a.outcome == b.outcome
     }
+}
 
-    Ok(())
+/// Compare two stores, the left one serving as the expectation for the right one.
+pub fn compare_report(
+    (left_name, left): (&str, &redb::Database),
+    (right_name, right): (&str, &redb::Database),
+    options: &CompareOptions,
+) -> anyhow::Result<ComparisonReport> {
+    let txs1: Vec<Expectation> = options
+        .read(left)?
+        .into_iter()
+        .map(Expectation::from)
+        .collect();
+    let txs2 = options.read(right)?;
+
+    report(&txs1, &txs2, (left_name, right_name), options)
+}
+
+/// Compare two runs of one store, the left one serving as the expectation for the right one.
+pub fn compare_runs_report(
+    (name, db): (&str, &redb::Database),
+    (left_run, right_run): (&str, &str),
+    options: &CompareOptions,
+) -> anyhow::Result<ComparisonReport> {
+    compare_store_runs_report((name, db, left_run), (name, db, right_run), options)
+}
+
+/// Compare a run of one store to a run of another, or of the same one, the left run serving
+/// as the expectation for the right one.
+pub fn compare_store_runs_report(
+    (left_name, left, left_run): (&str, &redb::Database, &str),
+    (right_name, right, right_run): (&str, &redb::Database, &str),
+    options: &CompareOptions,
+) -> anyhow::Result<ComparisonReport> {
+    let txs1: Vec<Expectation> = options
+        .read_run(left, Some(left_run))?
+        .into_iter()
+        .map(Expectation::from)
+        .collect();
+    let txs2 = options.read_run(right, Some(right_run))?;
+
+    report(
+        &txs1,
+        &txs2,
+        (
+            &format!("{left_name}@{left_run}"),
+            &format!("{right_name}@{right_run}"),
+        ),
+        options,
+    )
+}
+
+/// Compare a run of a store, or its latest run if `run` is `None`, to a list of expectations.
+pub fn compare_with_vec_report(
+    v: &[Expectation],
+    (name, db): (&str, &redb::Database),
+    run: Option<&str>,
+    options: &CompareOptions,
+) -> anyhow::Result<ComparisonReport> {
+    let txs1 = options.read_run(db, run)?;
+    let mut projected = Vec::with_capacity(v.len());
+    for expectation in v {
+        projected.extend(options.project_expectation(expectation)?);
+    }
+
+    report(&projected, &txs1, ("expected", name), options)
+}
+
+pub fn compare(
+    db1: redb::Database,
+    db2: redb::Database,
+    options: &CompareOptions,
+    quiet: bool,
+) -> anyhow::Result<()> {
+    check(
+        &compare_report(("left", &db1), ("right", &db2), options)?,
+        quiet,
+    )
 }
 
 pub fn compare_with_vec(
     db1: redb::Database,
-    v: &Vec<Transaction>,
+    v: &[Expectation],
+    options: &CompareOptions,
     quiet: bool,
 ) -> anyhow::Result<()> {
-    let read_txn1 = db1.begin_read()?;
-    let table1 = read_txn1.open_table(TABLE)?;
+    check(
+        &compare_with_vec_report(v, ("actual", &db1), None, options)?,
+        quiet,
+    )
+}
+
+fn check(report: &ComparisonReport, quiet: bool) -> anyhow::Result<()> {
+    if !quiet {
+        eprint!("{}", render::comparison(report, &RenderOptions::default()));
+    }
+
+    if !report.is_ok() {
+        bail!("Mismatch detected!")
+    }
+
+    Ok(())
+}
+
+/// Align `expectations` against `actual`. Within a group, the actual transactions aligned to
+/// the group are then assigned to its members according to the group's ordering rules.
+/// Returns the edits in terms of the flattened expectations, each with an optional note
+/// explaining why a structurally matching pair was rejected.
+fn align_expectations(
+    expectations: &[Expectation],
+    compiled: &[CompiledTx],
+    actual: &[Transaction],
+    options: &CompareOptions,
+) -> Vec<(Edit, Option<String>)> {
+    // For each flattened expectation, the index of the `Expectation` it came from.
+    let owner: Vec<usize> = expectations
+        .iter()
+        .enumerate()
+        .flat_map(|(e, expectation)| std::iter::repeat_n(e, expectation.txs().len()))
+        .collect();
+    let mut ranges = Vec::with_capacity(expectations.len());
+    let mut start = 0;
+    for expectation in expectations {
+        ranges.push(start..start + expectation.txs().len());
+        start += expectation.txs().len();
+    }
+
+    let edits = align(
+        compiled.len(),
+        actual.len(),
+        |i, j| {
+            ranges[owner[i]]
+                .clone()
+                .any(|k| compiled[k].matches(&actual[j], options))
+        },
+        |i, j| {
+            ranges[owner[i]]
+                .clone()
+                .any(|k| compiled[k].expected.kind == actual[j].kind)
+        },
+    );
+
+    let resolve = |(e, pool): (usize, Vec<usize>)| match &expectations[e] {
+        Expectation::Group(group) => {
+            resolve_group(group, ranges[e].clone(), pool, compiled, actual, options)
+        }
+        Expectation::Tx(_) => unreachable!("expectation {e} is not a group"),
+    };
+
+    let mut out = Vec::with_capacity(edits.len());
+    // The group currently being collected, and the actual transactions aligned to it so far.
+    let mut pending: Option<(usize, Vec<usize>)> = None;
+    for edit in edits {
+        let (i, j) = match edit {
+            Edit::Match { left, right } | Edit::Change { left, right } => (left, Some(right)),
+            Edit::Missing { left } => (left, None),
+            Edit::Extra { .. } => {
+                out.push((edit, None));
+                continue;
+            }
+        };
+
+        if !matches!(expectations[owner[i]], Expectation::Group(_)) {
+            out.extend(pending.take().map(resolve).into_iter().flatten());
+            out.push((edit, None));
+            continue;
+        }
+
+        match &mut pending {
+            Some((e, pool)) if *e == owner[i] => pool.extend(j),
+            _ => {
+                out.extend(pending.take().map(resolve).into_iter().flatten());
+                pending = Some((owner[i], j.into_iter().collect()));
+            }
+        }
+    }
+    out.extend(pending.take().map(resolve).into_iter().flatten());
 
-    let mut err = false;
-    for (a, tx2) in table1.iter()?.zip(v.iter()) {
-        let a = a?.1.value();
+    out
+}
 
-        let tx1: Transaction = serde_json::from_str(&a)?;
+/// Assign the actual transactions `pool` to the members of `group`, which occupy `members`
+/// in the flattened expectations.
+fn resolve_group(
+    group: &Group,
+    members: Range<usize>,
+    pool: Vec<usize>,
+    compiled: &[CompiledTx],
+    actual: &[Transaction],
+    options: &CompareOptions,
+) -> Vec<(Edit, Option<String>)> {
+    let members: Vec<usize> = members.collect();
+    let mut assigned: Vec<Option<usize>> = vec![None; members.len()];
+    let mut leftover = Vec::new();
 
-        if tx1 != *tx2 {
-            err = true;
-            if !quiet {
-                // eprintln!("{}: {:#?}", "Diff".red(), tx1.diff(&tx2));
-                eprintln!("{}: {:#?} {:#?}", "Diff".red(), tx1, tx2);
+    match group {
+        Group::Unordered(_) => {
+            // Maximum bipartite matching, so that a wildcard member doesn't take a
+            // transaction that only a more specific member could have matched.
+            let fits: Vec<Vec<bool>> = members
+                .iter()
+                .map(|&i| {
+                    pool.iter()
+                        .map(|&j| compiled[i].matches(&actual[j], options))
+                        .collect()
+                })
+                .collect();
+            let mut matched: Vec<Option<usize>> = vec![None; members.len()];
+            for (p, &j) in pool.iter().enumerate() {
+                let mut seen = vec![false; members.len()];
+                if !augment(p, &fits, &mut matched, &mut seen) {
+                    leftover.push(j);
+                }
+            }
+            for (k, p) in matched.into_iter().enumerate() {
+                assigned[k] = p.map(|p| pool[p]);
             }
+        }
+        Group::PerAddress(_) => {
+            for j in pool {
+                // Only the earliest outstanding member on this address may match.
+                let candidate = (0..members.len())
+                    .find(|&k| {
+                        assigned[k].is_none()
+                            && same_address(&compiled[members[k]], &actual[j], options)
+                    })
+                    .filter(|&k| compiled[members[k]].matches(&actual[j], options));
+
+                match candidate {
+                    Some(k) => assigned[k] = Some(j),
+                    None => leftover.push(j),
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(members.len() + leftover.len());
+    for (k, &i) in members.iter().enumerate() {
+        if let Some(j) = assigned[k] {
+            out.push((Edit::Match { left: i, right: j }, None));
+        } else if let Some(pos) = leftover
+            .iter()
+            .position(|&j| actual[j].kind == compiled[i].expected.kind)
+        {
+            let j = leftover.remove(pos);
+            let note = compiled[i].matches(&actual[j], options).then(|| {
+                format!(
+                    "out of order for address {:?}",
+                    actual[j]
+                        .params
+                        .first()
+                        .map(Param::as_text)
+                        .unwrap_or_default()
+                )
+            });
+            out.push((Edit::Change { left: i, right: j }, note));
         } else {
-            if !quiet {
-                eprintln!("{}: {:#?}", "Same".green(), tx1);
+            out.push((Edit::Missing { left: i }, None));
+        }
+    }
+    out.extend(
+        leftover
+            .into_iter()
+            .map(|j| (Edit::Extra { right: j }, None)),
+    );
+
+    out
+}
+
+/// Try to match pool entry `p` to a member, re-matching previously matched members as needed.
+fn augment(p: usize, fits: &[Vec<bool>], matched: &mut [Option<usize>], seen: &mut [bool]) -> bool {
+    for k in 0..fits.len() {
+        if fits[k][p] && !seen[k] {
+            seen[k] = true;
+            if matched[k].is_none_or(|other| augment(other, fits, matched, seen)) {
+                matched[k] = Some(p);
+                return true;
             }
         }
     }
+    false
+}
+
+fn same_address(expected: &CompiledTx, tx: &Transaction, options: &CompareOptions) -> bool {
+    match (expected.expected.params.is_empty(), tx.params.first()) {
+        (true, None) => true,
+        (false, Some(addr)) => expected.param_matches(0, addr, options),
+        _ => false,
+    }
+}
 
-    if err {
-        bail!("Mismatch detected!")
+/// Align `left` against `right` and report every step. The report is ok if every transaction
+/// matched its expectation, the captured variables were consistent, and the lengths agree.
+fn report(
+    expectations: &[Expectation],
+    right: &[Transaction],
+    (left_name, right_name): (&str, &str),
+    options: &CompareOptions,
+) -> anyhow::Result<ComparisonReport> {
+    let left: Vec<&ExpectedTx> = expectations.iter().flat_map(Expectation::txs).collect();
+    let compiled = left
+        .iter()
+        .map(|tx| tx.compile())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let edits = align_expectations(expectations, &compiled, right, options);
+
+    let mut entries = Vec::with_capacity(edits.len());
+    let mut captures = Captures::new();
+    for (edit, note) in edits {
+        entries.push(match edit {
+            Edit::Match { left: i, right: j } => {
+                match compiled[i].bind(&right[j], &mut captures, options) {
+                    Ok(()) => Entry::Matched {
+                        left_index: i,
+                        right_index: j,
+                        actual: right[j].clone(),
+                    },
+                    Err(conflicts) => Entry::Mismatched {
+                        left_index: i,
+                        right_index: j,
+                        expected: left[i].clone(),
+                        actual: right[j].clone(),
+                        notes: conflicts,
+                        fields: Vec::new(),
+                    },
+                }
+            }
+            Edit::Change { left: i, right: j } => Entry::Mismatched {
+                left_index: i,
+                right_index: j,
+                expected: left[i].clone(),
+                actual: right[j].clone(),
+                notes: note.into_iter().collect(),
+                fields: txdiff::field_diffs(&compiled[i], &right[j], options),
+            },
+            Edit::Missing { left: i } => Entry::Missing {
+                left_index: i,
+                expected: left[i].clone(),
+            },
+            Edit::Extra { right: j } => Entry::Extra {
+                right_index: j,
+                actual: right[j].clone(),
+            },
+        });
     }
 
-    Ok(())
+    Ok(ComparisonReport {
+        left: left_name.to_string(),
+        right: right_name.to_string(),
+        justification: None,
+        left_len: left.len(),
+        right_len: right.len(),
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        expect::{ParamMatcher, Pattern},
+        testutil::tx,
+    };
+
+    /// The report's entries in brief: `=` matched, `~` mismatched, `-` missing, `+` extra,
+    /// with their left and right indices.
+    fn summary(report: &ComparisonReport) -> Vec<String> {
+        report
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::Matched {
+                    left_index,
+                    right_index,
+                    ..
+                } => format!("={left_index}/{right_index}"),
+                Entry::Mismatched {
+                    left_index,
+                    right_index,
+                    ..
+                } => format!("~{left_index}/{right_index}"),
+                Entry::Missing { left_index, .. } => format!("-{left_index}"),
+                Entry::Extra { right_index, .. } => format!("+{right_index}"),
+            })
+            .collect()
+    }
+
+    fn compare_summary(expected: &[Transaction], actual: &[Transaction]) -> Vec<String> {
+        let expected: Vec<Expectation> = expected.iter().cloned().map(Expectation::from).collect();
+        let options = CompareOptions::default();
+        summary(&report(&expected, actual, ("expected", "actual"), &options).unwrap())
+    }
+
+    fn abc() -> Vec<Transaction> {
+        vec![tx("filter", "a"), tx("get", "a"), tx("plan", "a")]
+    }
+
+    #[test]
+    fn missing_transactions() {
+        let [a, b, c] = abc().try_into().unwrap();
+        assert_eq!(
+            compare_summary(&abc(), &[b.clone(), c.clone()]),
+            ["-0", "=1/0", "=2/1"]
+        );
+        assert_eq!(
+            compare_summary(&abc(), &[a.clone(), c]),
+            ["=0/0", "-1", "=2/1"]
+        );
+        assert_eq!(compare_summary(&abc(), &[a, b]), ["=0/0", "=1/1", "-2"]);
+    }
+
+    #[test]
+    fn extra_transactions() {
+        let [a, b, c] = abc().try_into().unwrap();
+        let x = tx("list", "x");
+        assert_eq!(
+            compare_summary(&abc(), &[x.clone(), a.clone(), b.clone(), c.clone()]),
+            ["+0", "=0/1", "=1/2", "=2/3"]
+        );
+        assert_eq!(
+            compare_summary(&abc(), &[a.clone(), b.clone(), x.clone(), c.clone()]),
+            ["=0/0", "=1/1", "+2", "=2/3"]
+        );
+        assert_eq!(
+            compare_summary(&abc(), &[a, b, c, x]),
+            ["=0/0", "=1/1", "=2/2", "+3"]
+        );
+    }
+
+    #[test]
+    fn changed_transactions() {
+        let [a, b, c] = abc().try_into().unwrap();
+        let (a2, b2, c2) = (tx("filter", "z"), tx("get", "z"), tx("plan", "z"));
+        assert_eq!(
+            compare_summary(&abc(), &[a2, b.clone(), c.clone()]),
+            ["~0/0", "=1/1", "=2/2"]
+        );
+        assert_eq!(
+            compare_summary(&abc(), &[a.clone(), b2, c]),
+            ["=0/0", "~1/1", "=2/2"]
+        );
+        let report = report(
+            &abc().into_iter().map(Expectation::from).collect::<Vec<_>>(),
+            &[a, b, c2],
+            ("expected", "actual"),
+            &CompareOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(summary(&report), ["=0/0", "=1/1", "~2/2"]);
+        assert!(!report.is_ok());
+    }
+
+    fn group_report(expected: Vec<Expectation>, actual: &[Transaction]) -> ComparisonReport {
+        report(
+            &expected,
+            actual,
+            ("expected", "actual"),
+            &CompareOptions::default(),
+        )
+        .unwrap()
+    }
+
+    fn expected(kind: &str, param: ParamMatcher) -> ExpectedTx {
+        ExpectedTx {
+            kind: kind.into(),
+            params: vec![param],
+            response: None,
+            outcome: None,
+        }
+    }
+
+    #[test]
+    fn unordered_group_with_extra_interleaved() {
+        let group = Group::Unordered(vec![
+            tx("get", "a").into(),
+            tx("get", "b").into(),
+            tx("get", "c").into(),
+        ]);
+        let expectations = vec![
+            Expectation::from(tx("filter", "a")),
+            Expectation::Group(group),
+            Expectation::from(tx("plan", "a")),
+        ];
+        let actual = [
+            tx("filter", "a"),
+            tx("get", "c"),
+            tx("list", "x"),
+            tx("get", "a"),
+            tx("get", "b"),
+            tx("plan", "a"),
+        ];
+
+        // Every member matches; the extra is reported where the alignment met it, before the
+        // group is resolved.
+        let report = group_report(expectations, &actual);
+        assert_eq!(
+            summary(&report),
+            ["=0/0", "+2", "=1/3", "=2/4", "=3/1", "=4/5"]
+        );
+    }
+
+    #[test]
+    fn unordered_group_leaves_specific_members_their_match() {
+        // A greedy assignment would give "a" to the wildcard and leave the literal unmatched.
+        let group = Group::Unordered(vec![
+            expected("get", ParamMatcher::Pattern(Pattern::Any)),
+            expected("get", ParamMatcher::Literal("a".into())),
+        ]);
+        let report = group_report(
+            vec![Expectation::Group(group)],
+            &[tx("get", "a"), tx("get", "b")],
+        );
+        assert_eq!(summary(&report), ["=0/1", "=1/0"]);
+    }
+
+    #[test]
+    fn unordered_group_with_a_missing_member() {
+        let group = Group::Unordered(vec![tx("get", "a").into(), tx("get", "b").into()]);
+        let report = group_report(vec![Expectation::Group(group)], &[tx("get", "b")]);
+        assert_eq!(summary(&report), ["-0", "=1/0"]);
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn per_address_group_keeps_order_per_address() {
+        let op = |addr: &str, n: &str| Transaction {
+            kind: "op".into(),
+            params: vec![Param::Text(addr.into()), Param::Text(n.into())],
+            ..Default::default()
+        };
+        let group = || {
+            Group::PerAddress(vec![
+                op("a", "1").into(),
+                op("b", "1").into(),
+                op("a", "2").into(),
+            ])
+        };
+
+        let interleaved = group_report(
+            vec![Expectation::Group(group())],
+            &[op("b", "1"), op("a", "1"), op("a", "2")],
+        );
+        assert!(interleaved.is_ok(), "{:?}", summary(&interleaved));
+
+        let reordered = group_report(
+            vec![Expectation::Group(group())],
+            &[op("a", "2"), op("b", "1"), op("a", "1")],
+        );
+        assert!(!reordered.is_ok());
+        let notes: Vec<&String> = reordered
+            .entries
+            .iter()
+            .flat_map(|entry| match entry {
+                Entry::Mismatched { notes, .. } => notes.iter().collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+        assert!(
+            notes.iter().any(|note| note.contains("out of order")),
+            "{notes:?}"
+        );
+    }
+
+    fn plan(params: &[&str]) -> Transaction {
+        Transaction {
+            kind: "plan".into(),
+            params: params.iter().map(|p| Param::Text(p.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn projecting(indices: Vec<usize>) -> CompareOptions {
+        CompareOptions {
+            projections: BTreeMap::from([("plan".to_string(), indices)]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn recording_matches_itself_under_projection() {
+        let options = projecting(vec![1]);
+        let tx = plan(&["scoreboard/a.ron", "current", "desired"]);
+
+        // What `Sequence::record` stores, and what a later check compares it against.
+        let recorded = Expectation::from(options.normalized(tx.clone()).unwrap());
+        let expected = options.project_expectation(&recorded).unwrap().unwrap();
+        let actual = options.project(tx).unwrap().unwrap();
+
+        let report = report(&[expected], &[actual], ("expected", "actual"), &options).unwrap();
+        assert!(report.is_ok());
+    }
+
+    #[test]
+    fn projection_beyond_params_is_an_error() {
+        let options = projecting(vec![0, 3]);
+        let err = options.project(plan(&["a", "b"])).unwrap_err();
+        assert!(err.to_string().contains("names param 3"), "{err}");
+
+        let expectation = Expectation::from(plan(&["a"]));
+        assert!(options.project_expectation(&expectation).is_err());
+    }
 }
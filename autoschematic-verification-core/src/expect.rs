@@ -0,0 +1,553 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{
+    crosscheck::CompareOptions,
+    param::Param,
+    tx::{Outcome, Transaction},
+};
+
+/// Values captured by `Regex` named groups and `Capture` patterns, by name.
+pub type Captures = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// Matches any param.
+    Any,
+    /// Matches exactly this string. Only needed for literals that would otherwise
+    /// read as a pattern, i.e. the string "Any".
+    Eq(String),
+    /// Matches if the whole param matches this regex. Named groups capture variables;
+    /// a group whose name is already captured must match the same value again.
+    Regex(String),
+    /// Matches any param and captures it under this name. If the name is already
+    /// captured, only matches the captured value.
+    Capture(String),
+}
+
+/// An expected param: a plain string literal, a typed `Param`, or a `Pattern`.
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ParamMatcher {
+    // Pattern must come first: RON hands the unit variant `Any` to untagged enums as the
+    // string "Any", and it must not be taken for a literal.
+    Pattern(Pattern),
+    /// Matches a param whose `Param::as_text` is this string, whatever its type.
+    /// Expectations recorded before params were typed consist of these. Those recorded `None`
+    /// as the empty string, so against an optional param `""` only matches `None`, and
+    /// `Some("")` takes a `Typed` matcher.
+    Literal(String),
+    /// Matches exactly this param, type included.
+    Typed(Param),
+}
+
+impl std::fmt::Debug for ParamMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamMatcher::Pattern(pattern) => pattern.fmt(f),
+            ParamMatcher::Literal(s) => s.fmt(f),
+            ParamMatcher::Typed(param) => param.fmt(f),
+        }
+    }
+}
+
+impl Serialize for ParamMatcher {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ParamMatcher::Pattern(pattern) => pattern.serialize(serializer),
+            ParamMatcher::Literal(s) if s == "Any" => Pattern::Eq(s.clone()).serialize(serializer),
+            ParamMatcher::Literal(s) => serializer.serialize_str(s),
+            ParamMatcher::Typed(param) => param.serialize(serializer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpectedTx {
+    pub kind: String,
+    pub params: Vec<ParamMatcher>,
+    /// The connector's expected response. If absent, the response isn't checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ParamMatcher>,
+    /// The expected outcome of the call. If absent, the outcome isn't checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<ExpectedOutcome>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExpectedOutcome {
+    Ok,
+    /// The call failed with this error chain, outermost context first.
+    Err(Vec<ParamMatcher>),
+}
+
+impl From<Outcome> for ExpectedOutcome {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Ok => ExpectedOutcome::Ok,
+            Outcome::Err(chain) => {
+                ExpectedOutcome::Err(chain.into_iter().map(ParamMatcher::Literal).collect())
+            }
+        }
+    }
+}
+
+/// An entry in a sequence's `expected_txs`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Expectation {
+    Tx(ExpectedTx),
+    Group(Group),
+}
+
+/// A run of expected transactions whose order is not fully determined.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Group {
+    /// The transactions may occur in any order.
+    Unordered(Vec<ExpectedTx>),
+    /// Transactions on the same address (`params[0]`) must occur in the given order;
+    /// transactions on different addresses may interleave freely.
+    PerAddress(Vec<ExpectedTx>),
+}
+
+impl Group {
+    pub fn members(&self) -> &[ExpectedTx] {
+        match self {
+            Group::Unordered(txs) | Group::PerAddress(txs) => txs,
+        }
+    }
+}
+
+impl Expectation {
+    pub fn txs(&self) -> &[ExpectedTx] {
+        match self {
+            Expectation::Tx(tx) => std::slice::from_ref(tx),
+            Expectation::Group(group) => group.members(),
+        }
+    }
+}
+
+impl From<Transaction> for Expectation {
+    fn from(tx: Transaction) -> Self {
+        Expectation::Tx(tx.into())
+    }
+}
+
+impl From<Transaction> for ExpectedTx {
+    fn from(tx: Transaction) -> Self {
+        ExpectedTx {
+            kind: tx.kind,
+            params: tx.params.into_iter().map(ParamMatcher::Typed).collect(),
+            response: tx.response.map(ParamMatcher::Literal),
+            outcome: tx.outcome.map(ExpectedOutcome::from),
+        }
+    }
+}
+
+impl ExpectedTx {
+    pub fn compile(&self) -> anyhow::Result<CompiledTx<'_>> {
+        let compile = |matcher: &ParamMatcher| match matcher {
+            ParamMatcher::Pattern(Pattern::Regex(re)) => Regex::new(&format!("^(?:{re})$"))
+                .map(Some)
+                .context(format!(
+                    "compiling regex {re:?} in {} expectation",
+                    self.kind
+                )),
+            _ => Ok(None),
+        };
+
+        let mut regexes = Vec::with_capacity(self.params.len());
+        for param in &self.params {
+            regexes.push(compile(param)?);
+        }
+        let response_regex = match &self.response {
+            Some(response) => compile(response)?,
+            None => None,
+        };
+        let mut error_regexes = Vec::new();
+        if let Some(ExpectedOutcome::Err(chain)) = &self.outcome {
+            for error in chain {
+                error_regexes.push(compile(error)?);
+            }
+        }
+
+        Ok(CompiledTx {
+            expected: self,
+            regexes,
+            response_regex,
+            error_regexes,
+        })
+    }
+}
+
+/// An `ExpectedTx` with its regexes compiled, ready to be matched against transactions.
+pub struct CompiledTx<'a> {
+    pub expected: &'a ExpectedTx,
+    regexes: Vec<Option<Regex>>,
+    response_regex: Option<Regex>,
+    error_regexes: Vec<Option<Regex>>,
+}
+
+/// Whether `matcher` matches a value with text `value`. `typed` is the value itself if it is a
+/// param; other values (responses, errors) are plain text.
+fn matcher_matches(
+    matcher: &ParamMatcher,
+    regex: Option<&Regex>,
+    (value, typed): (&str, Option<&Param>),
+    options: &CompareOptions,
+) -> bool {
+    match matcher {
+        ParamMatcher::Literal(s) | ParamMatcher::Pattern(Pattern::Eq(s)) => {
+            // The empty string stands for `None`; see `Literal`.
+            let some_empty = s.is_empty() && matches!(typed, Some(Param::Optional(Some(_))));
+            !some_empty && options.param_eq(s, value)
+        }
+        ParamMatcher::Typed(param) => match typed {
+            Some(typed) => options.value_eq(param, typed),
+            None => matches!(param, Param::Text(s) if options.param_eq(s, value)),
+        },
+        ParamMatcher::Pattern(Pattern::Any) | ParamMatcher::Pattern(Pattern::Capture(_)) => true,
+        ParamMatcher::Pattern(Pattern::Regex(_)) => regex.unwrap().is_match(value),
+    }
+}
+
+impl CompiledTx<'_> {
+    /// Whether param `i` matches `param`, without regard to previously captured values.
+    pub fn param_matches(&self, i: usize, param: &Param, options: &CompareOptions) -> bool {
+        matcher_matches(
+            &self.expected.params[i],
+            self.regexes[i].as_ref(),
+            (&param.as_text(), Some(param)),
+            options,
+        )
+    }
+
+    /// Whether the response matches, without regard to previously captured values.
+    /// An expectation without a response matches any response.
+    pub fn response_matches(&self, response: Option<&str>, options: &CompareOptions) -> bool {
+        match (&self.expected.response, response) {
+            (None, _) => true,
+            (Some(matcher), Some(response)) => matcher_matches(
+                matcher,
+                self.response_regex.as_ref(),
+                (response, None),
+                options,
+            ),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Whether the outcome matches, without regard to previously captured values.
+    /// An expectation without an outcome matches any outcome.
+    pub fn outcome_matches(&self, outcome: Option<&Outcome>, options: &CompareOptions) -> bool {
+        match (&self.expected.outcome, outcome) {
+            (None, _) => true,
+            (Some(ExpectedOutcome::Ok), Some(Outcome::Ok)) => true,
+            (Some(ExpectedOutcome::Err(expected)), Some(Outcome::Err(chain))) => {
+                expected.len() == chain.len()
+                    && expected.iter().zip(&self.error_regexes).zip(chain).all(
+                        |((matcher, regex), error)| {
+                            matcher_matches(matcher, regex.as_ref(), (error, None), options)
+                        },
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `tx` matches, without regard to previously captured values.
+    pub fn matches(&self, tx: &Transaction, options: &CompareOptions) -> bool {
+        self.expected.kind == tx.kind
+            && self.expected.params.len() == tx.params.len()
+            && tx
+                .params
+                .iter()
+                .enumerate()
+                .all(|(i, param)| self.param_matches(i, param, options))
+            && self.response_matches(tx.response.as_deref(), options)
+            && self.outcome_matches(tx.outcome.as_ref(), options)
+    }
+
+    /// Capture the variables of a transaction that `matches`. Fails with a description of
+    /// every variable whose captured value differs from an earlier capture.
+    pub fn bind(
+        &self,
+        tx: &Transaction,
+        captures: &mut Captures,
+        options: &CompareOptions,
+    ) -> Result<(), Vec<String>> {
+        let mut conflicts = Vec::new();
+        let mut check =
+            |name: &str, value: &str, eq: &dyn Fn(&str, &str) -> bool| match captures.get(name) {
+                Some(prev) if !eq(prev, value) => conflicts.push(format!(
+                    "{name} was captured as {prev:?}, but is {value:?} here"
+                )),
+                Some(_) => {}
+                None => {
+                    captures.insert(name.to_string(), value.to_string());
+                }
+            };
+
+        let response = match (&self.expected.response, &tx.response) {
+            (Some(matcher), Some(response)) => Some((
+                matcher,
+                self.response_regex.as_ref(),
+                Cow::Borrowed(response.as_str()),
+            )),
+            _ => None,
+        };
+        let params = self
+            .expected
+            .params
+            .iter()
+            .zip(&self.regexes)
+            .zip(&tx.params)
+            .map(|((matcher, regex), param)| (matcher, regex.as_ref(), param.as_text()));
+        let errors = match (&self.expected.outcome, &tx.outcome) {
+            (Some(ExpectedOutcome::Err(expected)), Some(Outcome::Err(chain))) => expected
+                .iter()
+                .zip(&self.error_regexes)
+                .zip(chain)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let errors = errors.into_iter().map(|((matcher, regex), error)| {
+            (matcher, regex.as_ref(), Cow::Borrowed(error.as_str()))
+        });
+
+        for (matcher, regex, value) in params.chain(response).chain(errors) {
+            match matcher {
+                ParamMatcher::Pattern(Pattern::Capture(name)) => {
+                    check(name, &value, &|a, b| options.param_eq(a, b));
+                }
+                ParamMatcher::Pattern(Pattern::Regex(_)) => {
+                    let re = regex.unwrap();
+                    if let Some(caps) = re.captures(&value) {
+                        for name in re.capture_names().flatten() {
+                            if let Some(value) = caps.name(name) {
+                                check(name, value.as_str(), &|a, b| a == b);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(conflicts)
+        }
+    }
+}
+
+/// A known and accepted deviation of one store from the shared expectations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoreOverride {
+    /// Why the store is allowed to deviate. Required, and shown whenever the override is used.
+    pub justification: String,
+    pub patches: Vec<Patch>,
+}
+
+/// An edit to the shared expectations. Indices always refer to the shared list, not to the
+/// result of earlier patches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Patch {
+    /// Replace the `len` expectations starting at `at` with `with`.
+    Replace {
+        at: usize,
+        len: usize,
+        with: Vec<Expectation>,
+    },
+    /// Insert `txs` before the expectation at `at`.
+    Insert { at: usize, txs: Vec<Expectation> },
+    /// Remove the `len` expectations starting at `at`.
+    Remove { at: usize, len: usize },
+}
+
+impl Patch {
+    /// The range of shared expectations this patch replaces, and what it replaces them with.
+    fn splice(&self) -> anyhow::Result<(std::ops::Range<usize>, &[Expectation])> {
+        let (at, len, with) = match self {
+            Patch::Replace { at, len, with } => (*at, *len, with.as_slice()),
+            Patch::Insert { at, txs } => (*at, 0, txs.as_slice()),
+            Patch::Remove { at, len } => (*at, *len, &[][..]),
+        };
+        let Some(end) = at.checked_add(len) else {
+            anyhow::bail!("Patch on {} expectations from {} is out of bounds", len, at);
+        };
+        Ok((at..end, with))
+    }
+}
+
+impl StoreOverride {
+    pub fn apply(&self, shared: &[Expectation]) -> anyhow::Result<Vec<Expectation>> {
+        if self.justification.trim().is_empty() {
+            anyhow::bail!("Store override has no justification");
+        }
+
+        let mut splices = self
+            .patches
+            .iter()
+            .map(Patch::splice)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        splices.sort_by_key(|(range, _)| (range.start, range.end));
+
+        let mut out = Vec::with_capacity(shared.len());
+        let mut cursor = 0;
+        for (range, with) in splices {
+            if range.start < cursor || range.end > shared.len() {
+                anyhow::bail!(
+                    "Patch on {}..{} overlaps another patch or is out of bounds (expected_txs has {} entries)",
+                    range.start,
+                    range.end,
+                    shared.len()
+                );
+            }
+            out.extend_from_slice(&shared[cursor..range.start]);
+            out.extend_from_slice(with);
+            cursor = range.end;
+        }
+        out.extend_from_slice(&shared[cursor..]);
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::tx;
+
+    fn matches(matcher: ParamMatcher, param: Param) -> bool {
+        let expected = ExpectedTx {
+            kind: "plan".into(),
+            params: vec![matcher],
+            response: None,
+            outcome: None,
+        };
+        expected
+            .compile()
+            .unwrap()
+            .param_matches(0, &param, &CompareOptions::default())
+    }
+
+    #[test]
+    fn empty_literal_only_matches_none() {
+        let none = Param::optional(None);
+        let some_empty = Param::optional(Some(Param::Text(String::new())));
+
+        assert!(matches(ParamMatcher::Literal(String::new()), none.clone()));
+        assert!(!matches(
+            ParamMatcher::Literal(String::new()),
+            some_empty.clone()
+        ));
+        assert!(matches(ParamMatcher::Typed(some_empty.clone()), some_empty));
+        assert!(matches(
+            ParamMatcher::Literal("a".into()),
+            Param::optional(Some(Param::Text("a".into())))
+        ));
+        assert!(!matches(ParamMatcher::Literal("a".into()), none));
+    }
+
+    fn shared(names: &str) -> Vec<Expectation> {
+        names
+            .chars()
+            .map(|c| Expectation::from(tx("get", &c.to_string())))
+            .collect()
+    }
+
+    fn apply(patches: Vec<Patch>) -> anyhow::Result<Vec<Expectation>> {
+        StoreOverride {
+            justification: "known to differ".into(),
+            patches,
+        }
+        .apply(&shared("abcd"))
+    }
+
+    #[test]
+    fn override_patches_refer_to_the_shared_list() {
+        let patched = apply(vec![
+            Patch::Remove { at: 3, len: 1 },
+            Patch::Replace {
+                at: 1,
+                len: 1,
+                with: shared("xy"),
+            },
+            Patch::Insert {
+                at: 0,
+                txs: shared("z"),
+            },
+        ])
+        .unwrap();
+        assert_eq!(patched, shared("zaxyc"));
+
+        assert_eq!(apply(Vec::new()).unwrap(), shared("abcd"));
+        let appended = apply(vec![Patch::Insert {
+            at: 4,
+            txs: shared("e"),
+        }]);
+        assert_eq!(appended.unwrap(), shared("abcde"));
+    }
+
+    #[test]
+    fn insert_and_replace_at_the_same_index() {
+        // The insert goes before the replaced expectations.
+        let patched = apply(vec![
+            Patch::Replace {
+                at: 2,
+                len: 1,
+                with: shared("x"),
+            },
+            Patch::Insert {
+                at: 2,
+                txs: shared("y"),
+            },
+        ])
+        .unwrap();
+        assert_eq!(patched, shared("abyxd"));
+    }
+
+    #[test]
+    fn overlapping_and_out_of_bounds_patches_are_errors() {
+        let overlapping = apply(vec![
+            Patch::Remove { at: 0, len: 2 },
+            Patch::Remove { at: 1, len: 2 },
+        ]);
+        assert!(overlapping.unwrap_err().to_string().contains("overlaps"));
+
+        let past_end = apply(vec![Patch::Remove { at: 3, len: 2 }]);
+        assert!(past_end.unwrap_err().to_string().contains("out of bounds"));
+        let past_end = apply(vec![Patch::Insert {
+            at: 5,
+            txs: Vec::new(),
+        }]);
+        assert!(past_end.is_err());
+
+        let overflowing = apply(vec![Patch::Replace {
+            at: usize::MAX,
+            len: 2,
+            with: Vec::new(),
+        }]);
+        assert!(
+            overflowing
+                .unwrap_err()
+                .to_string()
+                .contains("out of bounds")
+        );
+    }
+
+    #[test]
+    fn overrides_need_a_justification() {
+        let unjustified = StoreOverride {
+            justification: "  ".into(),
+            patches: Vec::new(),
+        };
+        let e = unjustified.apply(&shared("ab")).unwrap_err();
+        assert!(e.to_string().contains("no justification"), "{e}");
+    }
+}
@@ -0,0 +1,156 @@
+use std::{io::Write, path::Path};
+
+use anyhow::Context;
+
+use crate::tx::Transaction;
+
+/// Text formats for stores, one transaction per line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jsonl,
+    Ron,
+}
+
+impl Format {
+    /// The format of a file with this extension: `.jsonl` or `.ron`.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "jsonl" => Some(Format::Jsonl),
+            "ron" => Some(Format::Ron),
+            _ => None,
+        }
+    }
+}
+
+/// Write transactions one per line, in order. Each line is the transaction serialized
+/// compactly, fields in declaration order. Timestamps and timing differ on every run, so
+/// they're left out unless `timing` is set; exports of equal stores are then equal byte for
+/// byte.
+pub fn export(
+    txs: &[Transaction],
+    format: Format,
+    timing: bool,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    for tx in txs {
+        let tx = if timing {
+            tx.clone()
+        } else {
+            Transaction {
+                timestamp: None,
+                timing: None,
+                ..tx.clone()
+            }
+        };
+        let line = match format {
+            Format::Jsonl => serde_json::to_string(&tx)?,
+            Format::Ron => ron::to_string(&tx)?,
+        };
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+/// Read transactions written by `export`. Blank lines are skipped.
+pub fn import(input: &str, format: Format) -> anyhow::Result<Vec<Transaction>> {
+    let mut txs = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let tx = match format {
+            Format::Jsonl => serde_json::from_str(line).map_err(anyhow::Error::from),
+            Format::Ron => ron::from_str(line).map_err(anyhow::Error::from),
+        };
+        txs.push(tx.context(format!("line {}", i + 1))?);
+    }
+    Ok(txs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        param::Param,
+        tx::{Outcome, Timing},
+    };
+
+    fn txs() -> Vec<Transaction> {
+        vec![
+            Transaction {
+                kind: "plan".into(),
+                params: vec![
+                    Param::Path("scoreboard/state.ron".into()),
+                    Param::Optional(None),
+                    Param::Optional(Some(Box::new(Param::Ron("(random_int: 1)".into())))),
+                    Param::Bytes(vec![0xff, 0x00]),
+                ],
+                timestamp: Some(1_700_000_000_000_000_000),
+                response: Some("[\n    \"Set the state to 1\",\n]".into()),
+                outcome: Some(Outcome::Ok),
+                timing: Some(Timing {
+                    start: 1_700_000_000_000_000_000,
+                    end: 1_700_000_000_000_500_000,
+                    duration: 500_000,
+                }),
+            },
+            Transaction {
+                kind: "get".into(),
+                params: vec![Param::Text("line one\nline two".into())],
+                outcome: Some(Outcome::Err(vec!["outer".into(), "inner".into()])),
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn round_trip(format: Format, timing: bool) -> (String, Vec<Transaction>) {
+        let mut out = Vec::new();
+        export(&txs(), format, timing, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let imported = import(&text, format).unwrap();
+        (text, imported)
+    }
+
+    #[test]
+    fn round_trip_without_timing() {
+        for format in [Format::Jsonl, Format::Ron] {
+            let (text, imported) = round_trip(format, false);
+            assert_eq!(text.lines().count(), 2, "{format:?}: {text}");
+            assert_eq!(imported, txs(), "{format:?}");
+            assert!(
+                imported
+                    .iter()
+                    .all(|tx| tx.timestamp.is_none() && tx.timing.is_none()),
+                "{format:?}: {text}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip_with_timing() {
+        for format in [Format::Jsonl, Format::Ron] {
+            let (_, imported) = round_trip(format, true);
+            assert_eq!(imported, txs(), "{format:?}");
+            assert_eq!(imported[0].timestamp, txs()[0].timestamp, "{format:?}");
+            assert_eq!(imported[0].timing, txs()[0].timing, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn blank_lines_are_skipped_and_errors_name_the_line() {
+        let (text, _) = round_trip(Format::Jsonl, false);
+        let imported = import(&format!("\n{text}\n  \n"), Format::Jsonl).unwrap();
+        assert_eq!(imported, txs());
+
+        let e = import("\n{\"kind\": 1}\n", Format::Jsonl).unwrap_err();
+        assert_eq!(e.to_string(), "line 2");
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(Format::from_path(Path::new("a.jsonl")), Some(Format::Jsonl));
+        assert_eq!(Format::from_path(Path::new("dir/a.ron")), Some(Format::Ron));
+        assert_eq!(Format::from_path(Path::new("a.json")), None);
+        assert_eq!(Format::from_path(Path::new("a")), None);
+    }
+}
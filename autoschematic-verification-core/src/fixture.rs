@@ -0,0 +1,223 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+/// A step that prepares the repository, run by the testbench itself rather than as a command.
+/// Paths are relative to the testbench's working directory.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Fixture {
+    /// Copy a file, creating the destination's directory if needed.
+    Copy { from: PathBuf, to: PathBuf },
+    /// Write `contents` to a file, creating its directory if needed.
+    Write { path: PathBuf, contents: String },
+    /// Remove a file, if it exists.
+    Remove(PathBuf),
+    /// Stage files in git.
+    GitAdd(Vec<PathBuf>),
+    /// Unstage files in git, or everything staged if empty.
+    GitReset(Vec<PathBuf>),
+    /// Commit what's staged in git.
+    GitCommit { message: String },
+}
+
+impl Fixture {
+    pub fn apply(&self) -> anyhow::Result<()> {
+        self.apply_in(Path::new("."))
+    }
+
+    /// Apply the fixture with its paths relative to `dir`, and git run there.
+    fn apply_in(&self, dir: &Path) -> anyhow::Result<()> {
+        match self {
+            Fixture::Copy { from, to } => {
+                create_parent(&dir.join(to))?;
+                std::fs::copy(dir.join(from), dir.join(to)).context(format!(
+                    "copying {} to {}",
+                    from.display(),
+                    to.display()
+                ))?;
+            }
+            Fixture::Write { path, contents } => {
+                create_parent(&dir.join(path))?;
+                std::fs::write(dir.join(path), contents)
+                    .context(format!("writing {}", path.display()))?;
+            }
+            Fixture::Remove(path) => match std::fs::remove_file(dir.join(path)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).context(format!("removing {}", path.display())),
+            },
+            Fixture::GitAdd(paths) => {
+                if paths.is_empty() {
+                    bail!("GitAdd names no files");
+                }
+                git(dir, &["add", "--"], paths)?;
+            }
+            Fixture::GitReset(paths) if paths.is_empty() => git(dir, &["reset", "-q"], &[])?,
+            Fixture::GitReset(paths) => git(dir, &["reset", "-q", "--"], paths)?,
+            Fixture::GitCommit { message } => git(dir, &["commit", "-q", "-m", message], &[])?,
+        }
+        Ok(())
+    }
+}
+
+fn create_parent(path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).context(format!("mkdir {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// Run git non-interactively, failing with what it printed if it doesn't succeed.
+fn git(dir: &Path, args: &[&str], paths: &[PathBuf]) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .args(paths)
+        .stdin(Stdio::null())
+        .output()
+        .context("running git")?;
+    if !output.status.success() {
+        // Some failures, like `commit` with nothing staged, are only explained on stdout.
+        let printed = if output.stderr.is_empty() {
+            &output.stdout
+        } else {
+            &output.stderr
+        };
+        bail!(
+            "git {} failed: {}\n{}",
+            args[0],
+            output.status,
+            String::from_utf8_lossy(printed).trim_end()
+        );
+    }
+    Ok(())
+}
+
+impl fmt::Display for Fixture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let paths = |paths: &[PathBuf]| {
+            paths
+                .iter()
+                .map(|p| format!(" {}", p.display()))
+                .collect::<String>()
+        };
+        match self {
+            Fixture::Copy { from, to } => write!(f, "copy {} to {}", from.display(), to.display()),
+            Fixture::Write { path, .. } => write!(f, "write {}", path.display()),
+            Fixture::Remove(path) => write!(f, "remove {}", path.display()),
+            Fixture::GitAdd(p) => write!(f, "git add{}", paths(p)),
+            Fixture::GitReset(p) => write!(f, "git reset{}", paths(p)),
+            Fixture::GitCommit { message } => write!(f, "git commit -m {:?}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn read(dir: &TempDir, path: &str) -> Option<String> {
+        std::fs::read_to_string(dir.path().join(path)).ok()
+    }
+
+    #[test]
+    fn write_copy_and_remove() {
+        let dir = TempDir::new();
+        let apply = |fixture: Fixture| fixture.apply_in(dir.path());
+
+        apply(Fixture::Write {
+            path: "src/a.ron".into(),
+            contents: "(a: 1)".into(),
+        })
+        .unwrap();
+        apply(Fixture::Copy {
+            from: "src/a.ron".into(),
+            to: "scoreboard/nested/a.ron".into(),
+        })
+        .unwrap();
+        assert_eq!(
+            read(&dir, "scoreboard/nested/a.ron").as_deref(),
+            Some("(a: 1)")
+        );
+
+        apply(Fixture::Remove("src/a.ron".into())).unwrap();
+        assert_eq!(read(&dir, "src/a.ron"), None);
+        // Removing what isn't there is fine, copying it isn't.
+        apply(Fixture::Remove("src/a.ron".into())).unwrap();
+        let e = apply(Fixture::Copy {
+            from: "src/a.ron".into(),
+            to: "b.ron".into(),
+        })
+        .unwrap_err();
+        assert_eq!(e.to_string(), "copying src/a.ron to b.ron");
+    }
+
+    /// `git` in `dir`, returning what it printed.
+    fn git_output(dir: &TempDir, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(dir.path())
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}: {output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn git_add_reset_and_commit() {
+        let dir = TempDir::new();
+        git_output(&dir, &["init", "-q"]);
+        git_output(&dir, &["config", "user.name", "Testbench"]);
+        git_output(&dir, &["config", "user.email", "testbench@example.com"]);
+        let apply = |fixture: Fixture| fixture.apply_in(dir.path());
+        let staged = || git_output(&dir, &["diff", "--cached", "--name-only"]);
+
+        for path in ["a.ron", "b.ron"] {
+            apply(Fixture::Write {
+                path: path.into(),
+                contents: String::new(),
+            })
+            .unwrap();
+        }
+        apply(Fixture::GitAdd(vec!["a.ron".into(), "b.ron".into()])).unwrap();
+        assert_eq!(staged(), "a.ron\nb.ron\n");
+        apply(Fixture::GitReset(vec!["b.ron".into()])).unwrap();
+        assert_eq!(staged(), "a.ron\n");
+
+        apply(Fixture::GitCommit {
+            message: "Add a".into(),
+        })
+        .unwrap();
+        assert_eq!(git_output(&dir, &["log", "--format=%s"]), "Add a\n");
+
+        apply(Fixture::GitAdd(vec!["b.ron".into()])).unwrap();
+        apply(Fixture::GitReset(Vec::new())).unwrap();
+        assert_eq!(staged(), "");
+
+        // Failures carry what git printed, even when that's on stdout.
+        let e = apply(Fixture::GitCommit {
+            message: "Nothing".into(),
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(e.starts_with("git commit failed"), "{e}");
+        assert!(e.contains("b.ron"), "{e}");
+        assert!(apply(Fixture::GitAdd(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let fixture = Fixture::GitAdd(vec!["a.ron".into(), "b.ron".into()]);
+        assert_eq!(fixture.to_string(), "git add a.ron b.ron");
+        let fixture = Fixture::GitCommit {
+            message: "Add a".into(),
+        };
+        assert_eq!(fixture.to_string(), "git commit -m \"Add a\"");
+    }
+}
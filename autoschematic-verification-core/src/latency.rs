@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crosscheck::CompareOptions,
+    report::{KindLatency, Latency, LatencyReport, LatencyViolation},
+};
+
+/// Limits on call latency, checked by `latency_report`. Transactions without timing are
+/// ignored.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LatencyThresholds {
+    /// Fail if a kind's p95 in any store exceeds its p95 in the first store by this factor,
+    /// e.g. `1.5`.
+    #[serde(default)]
+    pub max_p95_ratio: Option<f64>,
+    /// Fail if any call of these kinds takes longer than this many milliseconds, e.g.
+    /// `{"plan": 500}`.
+    #[serde(default)]
+    pub max_ms: BTreeMap<String, u64>,
+}
+
+impl LatencyThresholds {
+    pub fn is_set(&self) -> bool {
+        self.max_p95_ratio.is_some() || !self.max_ms.is_empty()
+    }
+
+    pub fn is_unset(&self) -> bool {
+        !self.is_set()
+    }
+}
+
+/// Summarize the latency of every kind of call in a run of every store, or their latest runs,
+/// and check it against the thresholds. Ratios are taken against the first store.
+pub fn latency_report(
+    stores: &[(&str, redb::Database)],
+    run: Option<&str>,
+    thresholds: &LatencyThresholds,
+    options: &CompareOptions,
+) -> anyhow::Result<LatencyReport> {
+    // durations[kind][s] holds store s's timed calls of that kind.
+    let mut durations: BTreeMap<String, Vec<Vec<u64>>> = BTreeMap::new();
+    for (s, (_, db)) in stores.iter().enumerate() {
+        for tx in options.read_run(db, run)? {
+            if let Some(timing) = tx.timing {
+                durations
+                    .entry(tx.kind)
+                    .or_insert_with(|| vec![Vec::new(); stores.len()])[s]
+                    .push(timing.duration);
+            }
+        }
+    }
+
+    let mut report = LatencyReport {
+        stores: stores.iter().map(|(name, _)| name.to_string()).collect(),
+        kinds: Vec::new(),
+        violations: Vec::new(),
+    };
+
+    for (kind, mut per_store) in durations {
+        let mut latencies = Vec::new();
+        for (s, durations) in per_store.iter_mut().enumerate() {
+            if durations.is_empty() {
+                continue;
+            }
+            durations.sort_unstable();
+            latencies.push(Latency {
+                store: stores[s].0.to_string(),
+                count: durations.len(),
+                p50: percentile(durations, 50),
+                p95: percentile(durations, 95),
+                max: durations[durations.len() - 1],
+            });
+        }
+
+        if let Some(&limit) = thresholds.max_ms.get(&kind) {
+            for latency in latencies.iter().filter(|l| l.max > limit * 1_000_000) {
+                report.violations.push(LatencyViolation {
+                    kind: kind.clone(),
+                    store: latency.store.clone(),
+                    message: format!(
+                        "slowest call took {}, over the limit of {limit}ms",
+                        format_nanos(latency.max)
+                    ),
+                });
+            }
+        }
+
+        if let (Some(ratio), Some(baseline)) = (
+            thresholds.max_p95_ratio,
+            latencies.iter().find(|l| l.store == stores[0].0),
+        ) {
+            for latency in latencies.iter().filter(|l| l.store != baseline.store) {
+                if latency.p95 as f64 > baseline.p95 as f64 * ratio {
+                    report.violations.push(LatencyViolation {
+                        kind: kind.clone(),
+                        store: latency.store.clone(),
+                        message: format!(
+                            "p95 of {} is over {ratio}x the p95 of {} in {}",
+                            format_nanos(latency.p95),
+                            format_nanos(baseline.p95),
+                            baseline.store
+                        ),
+                    });
+                }
+            }
+        }
+
+        report.kinds.push(KindLatency {
+            kind,
+            stores: latencies,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Nearest-rank percentile of non-empty, sorted `durations`.
+fn percentile(durations: &[u64], p: usize) -> u64 {
+    let rank = (durations.len() * p).div_ceil(100).max(1);
+    durations[rank - 1]
+}
+
+/// A duration in nanoseconds, in milliseconds with microsecond precision.
+pub fn format_nanos(nanos: u64) -> String {
+    format!("{:.3}ms", nanos as f64 / 1_000_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testutil::{TempDir, tx},
+        tx::{Timing, Transaction},
+    };
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[7], 95), 7);
+        assert_eq!(percentile(&[1, 2], 50), 1);
+        assert_eq!(percentile(&[1, 2], 95), 2);
+
+        let hundred: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&hundred, 50), 50);
+        assert_eq!(percentile(&hundred, 95), 95);
+        assert_eq!(percentile(&hundred, 100), 100);
+        let twenty: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&twenty, 95), 19);
+        assert_eq!(percentile(&twenty, 0), 1);
+    }
+
+    /// A store with a call of `kind` per duration, in milliseconds, and an untimed one.
+    fn store(dir: &TempDir, name: &str, kind: &str, millis: &[u64]) -> redb::Database {
+        let db = dir.db(name);
+        let mut txs: Vec<Transaction> = millis
+            .iter()
+            .map(|ms| Transaction {
+                timing: Some(Timing {
+                    start: 0,
+                    end: ms * 1_000_000,
+                    duration: ms * 1_000_000,
+                }),
+                ..tx(kind, "a")
+            })
+            .collect();
+        txs.push(tx(kind, "untimed"));
+        Transaction::write_all(&db, &txs).unwrap();
+        db
+    }
+
+    #[test]
+    fn report_checks_ratio_against_the_first_store_and_limits() {
+        let dir = TempDir::new();
+        let stores = [
+            ("fast", store(&dir, "fast.redb", "plan", &[10, 20, 30])),
+            ("slow", store(&dir, "slow.redb", "plan", &[10, 20, 90])),
+        ];
+        let thresholds = LatencyThresholds {
+            max_p95_ratio: Some(2.0),
+            max_ms: BTreeMap::from([("plan".to_string(), 50)]),
+        };
+        let report =
+            latency_report(&stores, None, &thresholds, &CompareOptions::default()).unwrap();
+
+        let plan = &report.kinds[0];
+        assert_eq!(plan.kind, "plan");
+        let slow = &plan.stores[1];
+        assert_eq!(
+            (slow.count, slow.p50, slow.max),
+            (3, 20_000_000, 90_000_000)
+        );
+
+        let violations: Vec<_> = report
+            .violations
+            .iter()
+            .map(|v| (v.store.as_str(), v.message.as_str()))
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                ("slow", "slowest call took 90.000ms, over the limit of 50ms"),
+                (
+                    "slow",
+                    "p95 of 90.000ms is over 2x the p95 of 30.000ms in fast"
+                ),
+            ]
+        );
+
+        let unset = LatencyThresholds::default();
+        assert!(unset.is_unset());
+        let report = latency_report(&stores, None, &unset, &CompareOptions::default()).unwrap();
+        assert!(report.violations.is_empty());
+    }
+}
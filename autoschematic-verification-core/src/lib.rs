@@ -1,6 +1,29 @@
+pub mod align;
 pub mod tx;
 pub mod crosscheck;
+pub mod expect;
+pub mod export;
+pub mod fixture;
+pub mod latency;
+pub mod metadata;
+pub mod normalize;
+pub mod nway;
+pub mod output;
+pub mod param;
+pub mod render;
+pub mod report;
+pub mod run;
+pub mod schema;
+pub mod semantic;
 pub mod sequence;
+pub mod step;
+#[cfg(test)]
+mod testutil;
+pub mod txdiff;
+pub mod writer;
 use redb::{TableDefinition};
 
-pub const TABLE: TableDefinition<u128, String> = TableDefinition::new("transactions");
\ No newline at end of file
+pub const TABLE: TableDefinition<u128, String> = TableDefinition::new("transactions");
+pub const METADATA_TABLE: TableDefinition<u128, String> = TableDefinition::new("metadata");
+pub const OUTPUT_TABLE: TableDefinition<u128, String> = TableDefinition::new("output");
+pub const RUNS_TABLE: TableDefinition<u128, String> = TableDefinition::new("runs");
\ No newline at end of file
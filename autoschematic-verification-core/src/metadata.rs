@@ -0,0 +1,89 @@
+use std::{collections::BTreeMap, path::Path};
+
+use redb::{ReadableDatabase, ReadableTable, TableDefinition, TableError};
+use serde::{Deserialize, Serialize};
+
+use crate::{run, schema};
+
+/// What produced a store: one row per connector process that wrote to it.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Metadata {
+    pub name: String,
+    pub prefix: String,
+    /// `SEED` from the connector's environment.
+    pub seed: Option<String>,
+    /// `PROTOCOL` from the connector's environment.
+    pub protocol: Option<String>,
+    pub pid: u32,
+    /// Versions of the crates that wrote the store, by crate name.
+    pub versions: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// Metadata for the current process.
+    pub fn current(name: &str, prefix: &Path) -> Metadata {
+        Metadata {
+            name: name.to_string(),
+            prefix: prefix.to_string_lossy().to_string(),
+            seed: std::env::var("SEED").ok(),
+            protocol: std::env::var("PROTOCOL").ok(),
+            pid: std::process::id(),
+            versions: BTreeMap::from([(
+                String::from(env!("CARGO_PKG_NAME")),
+                String::from(env!("CARGO_PKG_VERSION")),
+            )]),
+        }
+    }
+
+    pub fn with_version(mut self, name: &str, version: &str) -> Metadata {
+        self.versions.insert(name.to_string(), version.to_string());
+        self
+    }
+
+    /// Append this metadata to the store's latest run, unless it's already the last row.
+    pub fn write(&self, db: &redb::Database) -> anyhow::Result<()> {
+        let write_txn = db.begin_write()?;
+        schema::prepare_write(&write_txn)?;
+
+        {
+            let name = run::metadata_table(run::latest(&write_txn)?.as_deref());
+            let mut table = write_txn.open_table(TableDefinition::<u128, String>::new(&name))?;
+
+            let key = match table.last()? {
+                Some((_, last)) if serde_json::from_str::<Metadata>(&last.value())? == *self => {
+                    None
+                }
+                Some((last, _)) => Some(last.value() + 1),
+                None => Some(0),
+            };
+
+            if let Some(key) = key {
+                table.insert(key, &serde_json::to_string(self)?)?;
+            }
+        }
+
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    /// Every row of metadata of `run`, or of the latest run if `None`, oldest first. Stores
+    /// written before metadata was recorded have none.
+    pub fn read_run(db: &redb::Database, run: Option<&str>) -> anyhow::Result<Vec<Metadata>> {
+        let run = run::resolve(db, run)?;
+        let read_txn = db.begin_read()?;
+        let name = run::metadata_table(run.as_deref());
+        let table = match read_txn.open_table(TableDefinition::<u128, String>::new(&name)) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut metadata = Vec::new();
+        for row in table.iter()? {
+            metadata.push(serde_json::from_str(&row?.1.value())?);
+        }
+
+        Ok(metadata)
+    }
+}
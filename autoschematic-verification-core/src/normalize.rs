@@ -0,0 +1,214 @@
+use std::{borrow::Cow, sync::Arc};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    param::Param,
+    tx::{Outcome, Transaction},
+};
+
+/// A rewrite applied to text before it's compared.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Normalizer {
+    /// Replace every match of `regex` with `with`, which may refer to groups as `$1` or
+    /// `$name`. The name says what's being masked, e.g. "timestamp".
+    Replace {
+        name: String,
+        regex: String,
+        with: String,
+    },
+    /// Make absolute paths under this directory relative to it, matching whole path components
+    /// only. `"."` stands for the working directory of the testbench.
+    RelativePaths(String),
+    /// Collapse runs of spaces and tabs into one space, and drop trailing whitespace from
+    /// every line.
+    CollapseWhitespace,
+}
+
+enum Compiled {
+    Replace(Regex, String),
+    RelativePaths(String),
+    CollapseWhitespace(Regex),
+}
+
+/// Normalizers applied in order. Regexes are compiled once, when the list is built or
+/// deserialized.
+#[derive(Default, Clone)]
+pub struct Normalizers {
+    rules: Vec<Normalizer>,
+    compiled: Arc<Vec<Compiled>>,
+}
+
+impl Normalizers {
+    pub fn new(rules: Vec<Normalizer>) -> anyhow::Result<Normalizers> {
+        let compiled = rules
+            .iter()
+            .map(|rule| {
+                Ok(match rule {
+                    Normalizer::Replace { name, regex, with } => Compiled::Replace(
+                        Regex::new(regex).context(format!("compiling normalizer {}", name))?,
+                        with.clone(),
+                    ),
+                    Normalizer::RelativePaths(dir) => {
+                        let dir = match dir.as_str() {
+                            "." => std::env::current_dir()?.to_string_lossy().to_string(),
+                            dir => dir.to_string(),
+                        };
+                        let trimmed = dir.trim_end_matches('/');
+                        Compiled::RelativePaths(match trimmed {
+                            "" => dir,
+                            trimmed => trimmed.to_string(),
+                        })
+                    }
+                    Normalizer::CollapseWhitespace => {
+                        Compiled::CollapseWhitespace(Regex::new(r"[ \t]+").unwrap())
+                    }
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Normalizers {
+            rules,
+            compiled: Arc::new(compiled),
+        })
+    }
+
+    pub fn rules(&self) -> &[Normalizer] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// These normalizers, preceded by `first`.
+    pub fn after(&self, first: Vec<Normalizer>) -> anyhow::Result<Normalizers> {
+        Normalizers::new(first.into_iter().chain(self.rules.clone()).collect())
+    }
+
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for rule in self.compiled.iter() {
+            let next = match rule {
+                Compiled::Replace(regex, with) => regex.replace_all(&text, with.as_str()),
+                Compiled::RelativePaths(dir) => relative_to(&text, dir),
+                Compiled::CollapseWhitespace(regex) => {
+                    let collapsed = regex.replace_all(&text, " ");
+                    Cow::Owned(
+                        collapsed
+                            .split('\n')
+                            .map(str::trim_end)
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    )
+                }
+            };
+            if let Cow::Owned(next) = next {
+                text = Cow::Owned(next);
+            }
+        }
+        text
+    }
+
+    pub fn apply_param(&self, param: Param) -> Param {
+        match param {
+            Param::Path(p) => Param::Path(self.apply(&p.to_string_lossy()).into_owned().into()),
+            Param::Text(s) => Param::Text(self.apply(&s).into_owned()),
+            Param::Ron(s) => Param::Ron(self.apply(&s).into_owned()),
+            Param::Bytes(b) => Param::Bytes(b),
+            Param::Optional(p) => Param::Optional(p.map(|p| Box::new(self.apply_param(*p)))),
+        }
+    }
+
+    /// Normalize a transaction's params, response and error chain.
+    pub fn apply_tx(&self, tx: Transaction) -> Transaction {
+        if self.is_empty() {
+            return tx;
+        }
+        Transaction {
+            params: tx.params.into_iter().map(|p| self.apply_param(p)).collect(),
+            response: tx.response.map(|r| self.apply(&r).into_owned()),
+            outcome: tx.outcome.map(|outcome| match outcome {
+                Outcome::Ok => Outcome::Ok,
+                Outcome::Err(chain) => {
+                    Outcome::Err(chain.iter().map(|e| self.apply(e).into_owned()).collect())
+                }
+            }),
+            ..tx
+        }
+    }
+}
+
+/// Make paths under `dir` relative to it: `dir/a` becomes `a` and `dir` itself `.`. Only
+/// whole path components match, so a sibling like `dir2/a` is left alone.
+fn relative_to<'a>(text: &'a str, dir: &str) -> Cow<'a, str> {
+    let mut out = String::new();
+    let mut last = 0;
+    for (start, _) in text.match_indices(dir) {
+        let end = start + dir.len();
+        let (replacement, skip) = match text[end..].chars().next() {
+            None => (".", 0),
+            Some(c) if std::path::is_separator(c) => ("", c.len_utf8()),
+            Some(_) => continue,
+        };
+        out.push_str(&text[last..start]);
+        out.push_str(replacement);
+        last = end + skip;
+    }
+    if last == 0 {
+        return Cow::Borrowed(text);
+    }
+    out.push_str(&text[last..]);
+    Cow::Owned(out)
+}
+
+impl std::fmt::Debug for Normalizers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.rules.fmt(f)
+    }
+}
+
+impl Serialize for Normalizers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.rules.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Normalizers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rules = Vec::<Normalizer>::deserialize(deserializer)?;
+        Normalizers::new(rules).map_err(|e| serde::de::Error::custom(format!("{e:#}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relative(dir: &str, text: &str) -> String {
+        Normalizers::new(vec![Normalizer::RelativePaths(dir.to_string())])
+            .unwrap()
+            .apply(text)
+            .into_owned()
+    }
+
+    #[test]
+    fn relative_paths_under_dir() {
+        assert_eq!(relative("/home/u/proj", "/home/u/proj/a.ron"), "a.ron");
+        assert_eq!(relative("/home/u/proj/", "at /home/u/proj/x/b"), "at x/b");
+        assert_eq!(relative("/home/u/proj", "/home/u/proj"), ".");
+    }
+
+    #[test]
+    fn relative_paths_leave_siblings_alone() {
+        assert_eq!(
+            relative("/home/u/proj", "/home/u/project2/a.ron"),
+            "/home/u/project2/a.ron"
+        );
+        assert_eq!(
+            relative("/home/u/proj", "/home/u/proj2/a /home/u/proj/b"),
+            "/home/u/proj2/a b"
+        );
+    }
+}
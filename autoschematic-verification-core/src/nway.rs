@@ -0,0 +1,300 @@
+use anyhow::bail;
+
+use crate::{
+    align::{Edit, align},
+    crosscheck::CompareOptions,
+    expect::ExpectedTx,
+    render::{self, RenderOptions},
+    report::{Culprit, Disagreement, Entry, Faction, MultiStoreReport, Slot},
+    tx::Transaction,
+    txdiff,
+};
+
+/// Compare any number of stores at once. All stores are aligned against the one closest to
+/// all others, and at every slot where they disagree, the stores outside the majority view are
+/// named as culprits.
+pub fn compare_stores_report(
+    stores: &[(&str, redb::Database)],
+    options: &CompareOptions,
+) -> anyhow::Result<MultiStoreReport> {
+    let names: Vec<&str> = stores.iter().map(|(name, _)| *name).collect();
+    let txs = stores
+        .iter()
+        .map(|(_, db)| options.read(db))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    compare_txs_report(&names, &txs, options)
+}
+
+/// `compare_stores_report` on transactions already read, `txs[s]` being those of `names[s]`.
+fn compare_txs_report(
+    names: &[&str],
+    txs: &[Vec<Transaction>],
+    options: &CompareOptions,
+) -> anyhow::Result<MultiStoreReport> {
+    let mut report = MultiStoreReport {
+        stores: names.iter().map(|name| name.to_string()).collect(),
+        center: None,
+        disagreements: Vec::new(),
+        culprits: Vec::new(),
+        undecided: 0,
+    };
+
+    if txs.len() < 2 {
+        return Ok(report);
+    }
+
+    let pair_edits = |a: usize, b: usize| {
+        align(
+            txs[a].len(),
+            txs[b].len(),
+            |i, j| options.tx_eq(&txs[a][i], &txs[b][j]),
+            |i, j| txs[a][i].kind == txs[b][j].kind,
+        )
+    };
+
+    // The center is the store with the smallest total edit distance to all others.
+    let center = (0..txs.len())
+        .min_by_key(|&a| {
+            (0..txs.len())
+                .filter(|&b| b != a)
+                .map(|b| pair_edits(a, b).iter().filter(|e| !e.is_match()).count())
+                .sum::<usize>()
+        })
+        .unwrap();
+    report.center = Some(names[center].to_string());
+
+    let n = txs[center].len();
+    let slot_at = |k: usize| {
+        if k.is_multiple_of(2) {
+            Slot::Gap(k / 2)
+        } else {
+            Slot::Position(k / 2)
+        }
+    };
+
+    // slots[k][s] holds the indices of store s's transactions that fall into slot k.
+    let mut slots: Vec<Vec<Vec<usize>>> = vec![vec![Vec::new(); txs.len()]; 2 * n + 1];
+    for c in 0..n {
+        slots[2 * c + 1][center].push(c);
+    }
+    for s in (0..txs.len()).filter(|&s| s != center) {
+        let mut consumed = 0;
+        for edit in pair_edits(center, s) {
+            match edit {
+                Edit::Match { left, right } | Edit::Change { left, right } => {
+                    slots[2 * left + 1][s].push(right);
+                    consumed = left + 1;
+                }
+                Edit::Missing { left } => consumed = left + 1,
+                Edit::Extra { right } => slots[2 * consumed][s].push(right),
+            }
+        }
+    }
+
+    let same = |a: usize, b: usize, slot: &[Vec<usize>]| {
+        slot[a].len() == slot[b].len()
+            && slot[a]
+                .iter()
+                .zip(&slot[b])
+                .all(|(&i, &j)| options.tx_eq(&txs[a][i], &txs[b][j]))
+    };
+
+    let mut divergences = vec![0usize; txs.len()];
+    for (k, slot) in slots.iter().enumerate() {
+        let mut factions: Vec<Vec<usize>> = Vec::new();
+        for s in 0..txs.len() {
+            match factions.iter_mut().find(|f| same(f[0], s, slot)) {
+                Some(faction) => faction.push(s),
+                None => factions.push(vec![s]),
+            }
+        }
+
+        if factions.len() == 1 {
+            continue;
+        }
+
+        factions.sort_by_key(|f| std::cmp::Reverse(f.len()));
+        let has_majority = factions[0].len() * 2 > txs.len();
+        if has_majority {
+            for faction in &factions[1..] {
+                for &s in faction {
+                    divergences[s] += 1;
+                }
+            }
+        } else {
+            report.undecided += 1;
+        }
+
+        let reference = factions[0][0];
+        let mut reported = Vec::with_capacity(factions.len());
+        for (f, faction) in factions.iter().enumerate() {
+            let entries = if f == 0 {
+                Vec::new()
+            } else {
+                let s = faction[0];
+                slot_entries(
+                    (&txs[reference], &slot[reference]),
+                    (&txs[s], &slot[s]),
+                    options,
+                )?
+            };
+            reported.push(Faction {
+                stores: faction.iter().map(|&s| names[s].to_string()).collect(),
+                entries,
+            });
+        }
+
+        let slot = slot_at(k);
+        report.disagreements.push(Disagreement {
+            slot,
+            kind: match slot {
+                Slot::Position(c) => Some(txs[center][c].kind.clone()),
+                Slot::Gap(_) => None,
+            },
+            has_majority,
+            factions: reported,
+        });
+    }
+
+    report.culprits = (0..txs.len())
+        .filter(|&s| divergences[s] > 0)
+        .map(|s| Culprit {
+            store: names[s].to_string(),
+            divergences: divergences[s],
+        })
+        .collect();
+
+    Ok(report)
+}
+
+/// Like `compare_stores_report`, but prints the report unless `quiet` and fails on any
+/// disagreement.
+pub fn compare_stores(
+    stores: &[(&str, redb::Database)],
+    options: &CompareOptions,
+    quiet: bool,
+) -> anyhow::Result<()> {
+    let report = compare_stores_report(stores, options)?;
+
+    if !quiet {
+        eprint!(
+            "{}",
+            render::multi_store(&report, &RenderOptions::default())
+        );
+    }
+
+    if !report.is_ok() {
+        bail!("Mismatch detected!")
+    }
+
+    Ok(())
+}
+
+/// How `other`'s transactions in a slot differ from `reference`'s.
+fn slot_entries(
+    (reference, reference_slot): (&[Transaction], &[usize]),
+    (other, other_slot): (&[Transaction], &[usize]),
+    options: &CompareOptions,
+) -> anyhow::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+
+    for k in 0..reference_slot.len().max(other_slot.len()) {
+        match (reference_slot.get(k), other_slot.get(k)) {
+            (Some(&i), Some(&j)) => {
+                let expected = ExpectedTx::from(reference[i].clone());
+                let fields = txdiff::field_diffs(&expected.compile()?, &other[j], options);
+                entries.push(Entry::Mismatched {
+                    left_index: i,
+                    right_index: j,
+                    expected,
+                    actual: other[j].clone(),
+                    notes: Vec::new(),
+                    fields,
+                });
+            }
+            (Some(&i), None) => entries.push(Entry::Missing {
+                left_index: i,
+                expected: reference[i].clone().into(),
+            }),
+            (None, Some(&j)) => entries.push(Entry::Extra {
+                right_index: j,
+                actual: other[j].clone(),
+            }),
+            (None, None) => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::tx;
+
+    fn vote(stores: &[(&str, Vec<Transaction>)]) -> MultiStoreReport {
+        let names: Vec<&str> = stores.iter().map(|(name, _)| *name).collect();
+        let txs: Vec<Vec<Transaction>> = stores.iter().map(|(_, txs)| txs.clone()).collect();
+        compare_txs_report(&names, &txs, &CompareOptions::default()).unwrap()
+    }
+
+    fn culprits(report: &MultiStoreReport) -> Vec<(&str, usize)> {
+        report
+            .culprits
+            .iter()
+            .map(|c| (c.store.as_str(), c.divergences))
+            .collect()
+    }
+
+    #[test]
+    fn agreeing_stores() {
+        let txs = vec![tx("filter", "a"), tx("get", "a")];
+        let report = vote(&[("a", txs.clone()), ("b", txs.clone()), ("c", txs)]);
+        assert!(report.is_ok());
+        assert!(report.culprits.is_empty());
+    }
+
+    #[test]
+    fn three_stores_outvote_a_changed_transaction() {
+        let good = vec![tx("filter", "a"), tx("get", "a"), tx("plan", "a")];
+        let bad = vec![tx("filter", "a"), tx("get", "z"), tx("plan", "a")];
+        let report = vote(&[("tarpc", good.clone()), ("grpc", bad), ("local", good)]);
+
+        assert!(!report.is_ok());
+        assert_eq!(culprits(&report), [("grpc", 1)]);
+        assert_eq!(report.undecided, 0);
+        let [disagreement] = report.disagreements.as_slice() else {
+            panic!("{:?}", report.disagreements);
+        };
+        assert_eq!(disagreement.slot, Slot::Position(1));
+        assert_eq!(disagreement.kind.as_deref(), Some("get"));
+        assert!(disagreement.has_majority);
+        assert_eq!(disagreement.factions[0].stores, ["tarpc", "local"]);
+        assert_eq!(disagreement.factions[1].stores, ["grpc"]);
+    }
+
+    #[test]
+    fn three_stores_outvote_an_extra_transaction() {
+        let good = vec![tx("filter", "a"), tx("plan", "a")];
+        let extra = vec![tx("filter", "a"), tx("list", "x"), tx("plan", "a")];
+        let report = vote(&[("tarpc", extra), ("grpc", good.clone()), ("local", good)]);
+
+        assert_eq!(culprits(&report), [("tarpc", 1)]);
+        assert_eq!(report.disagreements.len(), 1);
+        assert_eq!(report.disagreements[0].slot, Slot::Gap(1));
+    }
+
+    #[test]
+    fn two_stores_have_no_majority() {
+        let report = vote(&[
+            ("tarpc", vec![tx("get", "a")]),
+            ("grpc", vec![tx("get", "b")]),
+        ]);
+
+        assert!(!report.is_ok());
+        assert!(report.culprits.is_empty());
+        assert_eq!(report.undecided, 1);
+        assert!(!report.disagreements[0].has_majority);
+    }
+}
@@ -0,0 +1,214 @@
+use std::path::Path;
+
+use anyhow::{Context, bail};
+use redb::{ReadableDatabase, ReadableTable, TableDefinition, TableError};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+
+use crate::{run, schema};
+
+/// An assertion on the output of a command step.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum OutputMatcher {
+    /// Passes if the output contains this string.
+    Contains(String),
+    /// Passes if this regex matches anywhere in the output.
+    Regex(String),
+    /// Passes if the output is exactly the contents of this file. Recording a sequence writes
+    /// the file.
+    Snapshot(String),
+}
+
+/// What `Snapshot` matchers do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotMode {
+    /// Compare the output to the snapshot.
+    Check,
+    /// Write the output to the snapshot.
+    Update,
+}
+
+impl OutputMatcher {
+    /// Check `output`, failing with a description of how it doesn't match.
+    pub fn check(&self, output: &str, mode: SnapshotMode) -> anyhow::Result<()> {
+        match self {
+            OutputMatcher::Contains(s) => {
+                if !output.contains(s.as_str()) {
+                    bail!("doesn't contain {:?}", s);
+                }
+            }
+            OutputMatcher::Regex(re) => {
+                let regex = Regex::new(re).context(format!("compiling regex {}", re))?;
+                if !regex.is_match(output) {
+                    bail!("doesn't match regex {:?}", re);
+                }
+            }
+            OutputMatcher::Snapshot(path) if mode == SnapshotMode::Update => {
+                if let Some(parent) = Path::new(path).parent() {
+                    std::fs::create_dir_all(parent)
+                        .context(format!("mkdir {}", parent.display()))?;
+                }
+                std::fs::write(path, output).context(format!("writing snapshot {}", path))?;
+            }
+            OutputMatcher::Snapshot(path) => {
+                let snapshot = match std::fs::read_to_string(path) {
+                    Ok(snapshot) => snapshot,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        bail!(
+                            "has no snapshot at {} yet; record the sequence to create it",
+                            path
+                        )
+                    }
+                    Err(e) => return Err(e).context(format!("reading snapshot {}", path)),
+                };
+                if snapshot != output {
+                    let diff = TextDiff::from_lines(snapshot.as_str(), output)
+                        .unified_diff()
+                        .header(path, "output")
+                        .to_string();
+                    bail!("differs from snapshot {}:\n{}", path, diff);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a command step printed, kept with the run so that a failed run can be looked into
+/// after the fact.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StepOutput {
+    /// The index of the step in the sequence's `commands`.
+    pub step: usize,
+    pub command: String,
+    /// `None` if the command was killed by a signal or timed out.
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl StepOutput {
+    /// Store the output of a run's steps, replacing any stored before.
+    pub fn write_run(db: &redb::Database, run: &str, outputs: &[StepOutput]) -> anyhow::Result<()> {
+        let write_txn = db.begin_write()?;
+        schema::prepare_write(&write_txn)?;
+
+        {
+            let name = run::output_table(Some(run));
+            let definition = TableDefinition::<u128, String>::new(&name);
+            write_txn.delete_table(definition)?;
+            let mut table = write_txn.open_table(definition)?;
+            for (key, output) in (0..).zip(outputs) {
+                table.insert(key, &serde_json::to_string(output)?)?;
+            }
+        }
+
+        write_txn.commit()?;
+
+        Ok(())
+    }
+
+    /// The output of the steps of `run`, or of the latest run if `None`.
+    pub fn read_run(db: &redb::Database, run: Option<&str>) -> anyhow::Result<Vec<StepOutput>> {
+        let run = run::resolve(db, run)?;
+        let read_txn = db.begin_read()?;
+        let name = run::output_table(run.as_deref());
+        let table = match read_txn.open_table(TableDefinition::<u128, String>::new(&name)) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut outputs = Vec::new();
+        for row in table.iter()? {
+            outputs.push(serde_json::from_str(&row?.1.value())?);
+        }
+
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn check(matcher: OutputMatcher, output: &str) -> anyhow::Result<()> {
+        matcher.check(output, SnapshotMode::Check)
+    }
+
+    #[test]
+    fn contains() {
+        check(OutputMatcher::Contains("lo w".into()), "hello world").unwrap();
+        let e = check(OutputMatcher::Contains("bye".into()), "hello world").unwrap_err();
+        assert_eq!(e.to_string(), "doesn't contain \"bye\"");
+    }
+
+    #[test]
+    fn regex() {
+        check(
+            OutputMatcher::Regex(r"Applied \d+ ops?".into()),
+            "ok\nApplied 3 ops\n",
+        )
+        .unwrap();
+        let e = check(OutputMatcher::Regex(r"^\d+$".into()), "3 ops").unwrap_err();
+        assert!(e.to_string().contains("doesn't match regex"), "{e}");
+        let e = check(OutputMatcher::Regex("(".into()), "").unwrap_err();
+        assert!(e.to_string().contains("compiling regex"), "{e}");
+    }
+
+    #[test]
+    fn snapshot_update_then_check() {
+        let dir = TempDir::new();
+        let path = dir.path().join("snapshots/plan.txt");
+        let snapshot = OutputMatcher::Snapshot(path.to_string_lossy().into_owned());
+
+        let e = check(snapshot.clone(), "a\n").unwrap_err();
+        assert!(e.to_string().contains("has no snapshot"), "{e}");
+
+        snapshot.check("a\nb\n", SnapshotMode::Update).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\n");
+        check(snapshot.clone(), "a\nb\n").unwrap();
+
+        let e = check(snapshot, "a\nc\n").unwrap_err().to_string();
+        assert!(e.contains("differs from snapshot"), "{e}");
+        assert!(e.contains("-b\n+c\n"), "{e}");
+    }
+
+    fn output(step: usize, stdout: &str) -> StepOutput {
+        StepOutput {
+            step,
+            command: format!("echo {stdout}"),
+            exit_code: Some(0),
+            timed_out: false,
+            stdout: format!("{stdout}\n"),
+            stderr: String::new(),
+        }
+    }
+
+    #[test]
+    fn step_output_round_trip() {
+        let dir = TempDir::new();
+        let db = dir.db("store.redb");
+        run::begin(&db, "first").unwrap();
+        run::begin(&db, "second").unwrap();
+        assert!(StepOutput::read_run(&db, None).unwrap().is_empty());
+
+        let first = vec![output(0, "a"), output(2, "b")];
+        StepOutput::write_run(&db, "first", &first).unwrap();
+        StepOutput::write_run(&db, "second", &[output(0, "x"), output(1, "y")]).unwrap();
+        // Writing again replaces what was there.
+        StepOutput::write_run(&db, "second", &[output(0, "c")]).unwrap();
+
+        assert_eq!(StepOutput::read_run(&db, Some("first")).unwrap(), first);
+        assert_eq!(StepOutput::read_run(&db, None).unwrap(), [output(0, "c")]);
+        assert!(StepOutput::read_run(&db, Some("third")).is_err());
+
+        run::prune(&db, 1).unwrap();
+        run::begin(&db, "first").unwrap();
+        assert!(StepOutput::read_run(&db, Some("first")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use diff::Diff;
+use serde::{Deserialize, Serialize};
+
+/// An argument of a connector call, recorded as it crossed the bridge.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Diff)]
+#[serde(from = "StoredParam")]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub enum Param {
+    Path(PathBuf),
+    /// UTF-8 text.
+    Text(String),
+    /// Bytes that aren't valid UTF-8.
+    Bytes(Vec<u8>),
+    /// UTF-8 text in RON syntax, e.g. a resource body.
+    Ron(String),
+    Optional(Option<Box<Param>>),
+}
+
+/// Params as stores hold them: tagged, or as plain strings in stores written before params
+/// were typed.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredParam {
+    Legacy(String),
+    Typed(TypedParam),
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Param")]
+enum TypedParam {
+    Path(PathBuf),
+    Text(String),
+    Bytes(Vec<u8>),
+    Ron(String),
+    Optional(Option<Box<Param>>),
+}
+
+impl From<StoredParam> for Param {
+    fn from(param: StoredParam) -> Self {
+        match param {
+            StoredParam::Legacy(s) => Param::Text(s),
+            StoredParam::Typed(TypedParam::Path(p)) => Param::Path(p),
+            StoredParam::Typed(TypedParam::Text(s)) => Param::Text(s),
+            StoredParam::Typed(TypedParam::Bytes(b)) => Param::Bytes(b),
+            StoredParam::Typed(TypedParam::Ron(s)) => Param::Ron(s),
+            StoredParam::Typed(TypedParam::Optional(p)) => Param::Optional(p),
+        }
+    }
+}
+
+impl Param {
+    pub fn path(path: &Path) -> Param {
+        Param::Path(path.to_path_buf())
+    }
+
+    /// `Text` if `bytes` is UTF-8, `Bytes` otherwise.
+    pub fn utf8(bytes: &[u8]) -> Param {
+        match str::from_utf8(bytes) {
+            Ok(s) => Param::Text(s.to_string()),
+            Err(_) => Param::Bytes(bytes.to_vec()),
+        }
+    }
+
+    /// `Ron` if `bytes` is UTF-8, `Bytes` otherwise.
+    pub fn ron(bytes: &[u8]) -> Param {
+        match str::from_utf8(bytes) {
+            Ok(s) => Param::Ron(s.to_string()),
+            Err(_) => Param::Bytes(bytes.to_vec()),
+        }
+    }
+
+    pub fn optional(param: Option<Param>) -> Param {
+        Param::Optional(param.map(Box::new))
+    }
+
+    /// The param as the plain string stores recorded before params were typed:
+    /// paths and bytes converted lossily, and `None` as the empty string.
+    pub fn as_text(&self) -> Cow<'_, str> {
+        match self {
+            Param::Path(p) => p.to_string_lossy(),
+            Param::Text(s) | Param::Ron(s) => Cow::Borrowed(s),
+            Param::Bytes(b) => String::from_utf8_lossy(b),
+            Param::Optional(None) => Cow::Borrowed(""),
+            Param::Optional(Some(p)) => p.as_text(),
+        }
+    }
+
+    /// A short description of the param's type, e.g. "some ron".
+    pub fn type_name(&self) -> String {
+        match self {
+            Param::Path(_) => String::from("path"),
+            Param::Text(_) => String::from("text"),
+            Param::Bytes(_) => String::from("bytes"),
+            Param::Ron(_) => String::from("ron"),
+            Param::Optional(None) => String::from("none"),
+            Param::Optional(Some(p)) => format!("some {}", p.type_name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(json: &str) -> Param {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn plain_strings_are_legacy_text() {
+        assert_eq!(stored(r#""a/b.ron""#), Param::Text("a/b.ron".into()));
+        assert_eq!(stored(r#""""#), Param::Text(String::new()));
+        // A legacy string that looks like a tag is still just text.
+        assert_eq!(stored(r#""Path""#), Param::Text("Path".into()));
+    }
+
+    #[test]
+    fn typed_params_round_trip() {
+        let params = [
+            Param::Path("a/b.ron".into()),
+            Param::Text("text".into()),
+            Param::Bytes(vec![0xff, 0]),
+            Param::Ron("(a: 1)".into()),
+            Param::Optional(None),
+            Param::optional(Some(Param::optional(Some(Param::Text("nested".into()))))),
+        ];
+        for param in params {
+            let json = serde_json::to_string(&param).unwrap();
+            assert_eq!(stored(&json), param, "{json}");
+        }
+        assert_eq!(stored(r#"{"Path":"a"}"#), Param::Path("a".into()));
+    }
+
+    #[test]
+    fn legacy_text_of_typed_params() {
+        assert_eq!(Param::Bytes(vec![b'a', 0xff]).as_text(), "a\u{fffd}");
+        assert_eq!(Param::Optional(None).as_text(), "");
+        assert_eq!(
+            Param::optional(Some(Param::Ron("(a: 1)".into()))).as_text(),
+            "(a: 1)"
+        );
+    }
+
+    #[test]
+    fn utf8_falls_back_to_bytes() {
+        assert_eq!(Param::utf8(b"abc"), Param::Text("abc".into()));
+        assert_eq!(Param::ron(b"(a: 1)"), Param::Ron("(a: 1)".into()));
+        assert_eq!(Param::utf8(&[0xff]), Param::Bytes(vec![0xff]));
+        assert_eq!(Param::ron(&[0xff]), Param::Bytes(vec![0xff]));
+    }
+}
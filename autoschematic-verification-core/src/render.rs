@@ -0,0 +1,779 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use colored::{ColoredString, Colorize};
+use similar::{ChangeTag, DiffTag, TextDiff};
+
+use crate::{
+    expect::{ExpectedOutcome, ExpectedTx, ParamMatcher, Pattern},
+    latency::format_nanos,
+    param::Param,
+    report::{
+        ComparisonReport, Entry, LatencyReport, MultiStoreReport, Report, Slot, StoreMetadata,
+    },
+    tx::Transaction,
+    txdiff::FieldDiff,
+};
+
+/// How reports are laid out on the terminal.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    /// Total width of a line, both columns included.
+    pub width: usize,
+    /// Number of matching transactions to show before and after each mismatch.
+    pub context: usize,
+    /// Show every transaction, not only mismatches and their context.
+    pub all: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            width: terminal_width(),
+            context: 0,
+            all: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Width of each of the two columns.
+    fn column(&self) -> usize {
+        (self.width.saturating_sub(3) / 2).max(20)
+    }
+}
+
+/// The terminal width according to `COLUMNS`, or 120 if it isn't set.
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(120)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Plain,
+    Bold,
+    Dim,
+    Removed,
+    Inserted,
+    /// The characters that differ within a removed line.
+    RemovedEmph,
+    /// The characters that differ within an inserted line.
+    InsertedEmph,
+}
+
+fn paint(text: &str, style: Style) -> ColoredString {
+    match style {
+        Style::Plain => text.normal(),
+        Style::Bold => text.bold(),
+        Style::Dim => text.dimmed(),
+        Style::Removed => text.red(),
+        Style::Inserted => text.green(),
+        Style::RemovedEmph => text.white().on_red(),
+        Style::InsertedEmph => text.black().on_green(),
+    }
+}
+
+/// The contents of one column of a row: a run of styled spans.
+type Cell = Vec<(String, Style)>;
+
+fn cell(text: impl Into<String>, style: Style) -> Cell {
+    vec![(text.into(), style)]
+}
+
+enum Line {
+    /// Spans both columns, e.g. a header.
+    Full(String),
+    /// A row of the two columns, wrapped to the column width.
+    Split(Cell, Cell),
+    /// A row of the two columns, truncated to the column width.
+    Summary(Cell, Cell),
+}
+
+pub fn render(report: &Report, options: &RenderOptions) -> String {
+    match report {
+        Report::Comparison(report) => comparison(report, options),
+        Report::MultiStore(report) => multi_store(report, options),
+        Report::Latency(report) => latency(report, options),
+        Report::Metadata(report) => metadata(report),
+    }
+}
+
+/// Render a comparison side by side, expectations on the left. Only mismatches and
+/// `options.context` matching transactions around them are shown, unless `options.all`.
+pub fn comparison(report: &ComparisonReport, options: &RenderOptions) -> String {
+    let mut out = String::new();
+    let (left, right) = (report.left.as_str(), report.right.as_str());
+
+    if let Some(justification) = &report.justification {
+        let _ = writeln!(
+            out,
+            "{}: {right} deviates from {left}: {justification}",
+            "Override".yellow()
+        );
+    }
+
+    if report.is_ok() && !options.all {
+        let _ = writeln!(
+            out,
+            "{}: {right} matches {left} ({} transactions)",
+            "Same".green(),
+            report.right_len
+        );
+        return out;
+    }
+
+    let mismatches: Vec<usize> = (0..report.entries.len())
+        .filter(|&k| !report.entries[k].is_match())
+        .collect();
+    let shown = |k: usize| {
+        options.all
+            || mismatches
+                .iter()
+                .any(|&m| k + options.context >= m && k <= m + options.context)
+    };
+
+    let mut lines = vec![Line::Split(
+        cell(left, Style::Bold),
+        cell(right, Style::Bold),
+    )];
+    let mut hidden = 0;
+    for (k, entry) in report.entries.iter().enumerate() {
+        if !shown(k) {
+            hidden += 1;
+            continue;
+        }
+        if hidden > 0 {
+            lines.push(elided(hidden));
+            hidden = 0;
+        }
+        entry_lines(entry, (left, right), &mut lines);
+    }
+    if hidden > 0 {
+        lines.push(elided(hidden));
+    }
+
+    if report.left_len != report.right_len {
+        lines.push(Line::Full(format!(
+            "{}: {left} has {} transactions, {right} has {}",
+            "Length".red(),
+            report.left_len,
+            report.right_len
+        )));
+    }
+
+    layout(&lines, options, &mut out);
+    out
+}
+
+/// Render every disagreement between stores, each diverging faction side by side with the
+/// largest one.
+pub fn multi_store(report: &MultiStoreReport, options: &RenderOptions) -> String {
+    let mut out = String::new();
+    let mut lines = Vec::new();
+
+    for disagreement in &report.disagreements {
+        let label = match (disagreement.slot, &disagreement.kind) {
+            (Slot::Position(c), Some(kind)) => format!("position {c} ({kind})"),
+            (Slot::Position(c), None) => format!("position {c}"),
+            (Slot::Gap(c), _) => format!("gap before position {c}"),
+        };
+
+        let first = &disagreement.factions[0];
+        let first_names = first.stores.join(", ");
+        lines.push(Line::Full(if disagreement.has_majority {
+            format!("{} at {label}: majority [{first_names}]", "Diff".red())
+        } else {
+            format!("{} at {label}: no majority", "Diff".red())
+        }));
+
+        for faction in &disagreement.factions[1..] {
+            let names = faction.stores.join(", ");
+            lines.push(Line::Split(
+                cell(format!("[{first_names}]"), Style::Bold),
+                cell(format!("[{names}]"), Style::Bold),
+            ));
+            for entry in &faction.entries {
+                entry_lines(entry, (&first.stores[0], &faction.stores[0]), &mut lines);
+            }
+        }
+    }
+
+    for culprit in &report.culprits {
+        lines.push(Line::Full(format!(
+            "{}: {} diverged from the majority at {} slot(s)",
+            "Culprit".red(),
+            culprit.store,
+            culprit.divergences
+        )));
+    }
+    if report.undecided > 0 {
+        lines.push(Line::Full(format!(
+            "{}: no majority at {} slot(s) between {}",
+            "Undecided".red(),
+            report.undecided,
+            report.stores.join(", ")
+        )));
+    }
+
+    layout(&lines, options, &mut out);
+    out
+}
+
+/// Render latency as a table of kinds by stores, each cell reading "p50 / p95 / max (calls)",
+/// followed by any thresholds exceeded.
+pub fn latency(report: &LatencyReport, options: &RenderOptions) -> String {
+    let mut out = String::new();
+
+    let mut rows = vec![
+        std::iter::once(String::from("kind"))
+            .chain(report.stores.iter().cloned())
+            .collect::<Vec<_>>(),
+    ];
+    for kind in &report.kinds {
+        let mut row = vec![kind.kind.clone()];
+        for store in &report.stores {
+            row.push(match kind.stores.iter().find(|l| &l.store == store) {
+                Some(l) => format!(
+                    "{} / {} / {} ({})",
+                    format_nanos(l.p50),
+                    format_nanos(l.p95),
+                    format_nanos(l.max),
+                    l.count
+                ),
+                None => String::from("-"),
+            });
+        }
+        rows.push(row);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|c| rows.iter().map(|row| row[c].chars().count()).max().unwrap())
+        .collect();
+    for (r, row) in rows.iter().enumerate() {
+        let line: String = row
+            .iter()
+            .zip(&widths)
+            .map(|(text, &width)| format!("{text:width$}"))
+            .collect::<Vec<_>>()
+            .join("   ");
+        let line: String = line.trim_end().chars().take(options.width).collect();
+        let style = if r == 0 { Style::Bold } else { Style::Plain };
+        let _ = writeln!(out, "{}", paint(&line, style));
+    }
+
+    for violation in &report.violations {
+        let _ = writeln!(
+            out,
+            "{}: {} in {}: {}",
+            "Slow".red(),
+            violation.kind,
+            violation.store,
+            violation.message
+        );
+    }
+
+    out
+}
+
+/// Render what produced a store, one connector process per line.
+pub fn metadata(report: &StoreMetadata) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "{} of {}:", "Metadata".bold(), report.store);
+    if report.metadata.is_empty() {
+        let _ = writeln!(out, "  (none recorded)");
+    }
+    for m in &report.metadata {
+        let versions: Vec<String> = m
+            .versions
+            .iter()
+            .map(|(name, version)| format!("{name} {version}"))
+            .collect();
+        let _ = writeln!(
+            out,
+            "  {} at {}: SEED={} PROTOCOL={} pid {} ({})",
+            m.name,
+            m.prefix,
+            m.seed.as_deref().unwrap_or("-"),
+            m.protocol.as_deref().unwrap_or("-"),
+            m.pid,
+            versions.join(", ")
+        );
+    }
+
+    out
+}
+
+fn elided(count: usize) -> Line {
+    Line::Full(
+        format!("  ⋯ {count} matching transaction(s)")
+            .dimmed()
+            .to_string(),
+    )
+}
+
+fn entry_lines(entry: &Entry, (left, right): (&str, &str), lines: &mut Vec<Line>) {
+    match entry {
+        Entry::Matched {
+            left_index: i,
+            right_index: j,
+            actual,
+        } => {
+            let summary = summary(&actual.kind, actual.params.iter().map(brief));
+            lines.push(Line::Summary(
+                cell(format!("{i}: {summary}"), Style::Dim),
+                cell(format!("{j}: {summary}"), Style::Dim),
+            ));
+        }
+        Entry::Mismatched {
+            left_index: i,
+            right_index: j,
+            expected,
+            actual,
+            notes,
+            fields,
+        } => {
+            lines.push(Line::Full(format!(
+                "{} {left}[{i}] {right}[{j}] ({}):",
+                "Diff".red(),
+                expected.kind
+            )));
+            for note in notes {
+                lines.push(Line::Full(format!("  {}", note.yellow())));
+            }
+            mismatched_lines(expected, actual, fields, lines);
+        }
+        Entry::Missing {
+            left_index: i,
+            expected,
+        } => {
+            lines.push(Line::Full(format!(
+                "{} {left}[{i}] not in {right}:",
+                "Missing".red()
+            )));
+            lines.push(Line::Split(
+                cell(format!("kind: {}", expected.kind), Style::Removed),
+                Vec::new(),
+            ));
+            for (label, matcher) in expected_fields(expected) {
+                for cell in matcher_cells(&label, &matcher, Style::Removed) {
+                    lines.push(Line::Split(cell, Vec::new()));
+                }
+            }
+        }
+        Entry::Extra {
+            right_index: j,
+            actual,
+        } => {
+            lines.push(Line::Full(format!(
+                "{} {right}[{j}] not in {left}:",
+                "Extra".red()
+            )));
+            lines.push(Line::Split(
+                Vec::new(),
+                cell(format!("kind: {}", actual.kind), Style::Inserted),
+            ));
+            for (label, param) in actual_fields(actual) {
+                for cell in param_cells(&label, &param.as_text(), Style::Inserted) {
+                    lines.push(Line::Split(Vec::new(), cell));
+                }
+            }
+        }
+    }
+}
+
+/// The params, response and outcome of an expectation, labelled.
+fn expected_fields(expected: &ExpectedTx) -> Vec<(String, ParamMatcher)> {
+    let mut fields: Vec<_> = expected
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| (format!("[{i}]"), param.clone()))
+        .collect();
+    if let Some(response) = &expected.response {
+        fields.push((String::from("response:"), response.clone()));
+    }
+    if let Some(outcome) = outcome_matcher(&expected.outcome) {
+        fields.push((String::from("outcome:"), outcome));
+    }
+    fields
+}
+
+/// The params, response and outcome of a transaction, labelled.
+fn actual_fields(actual: &Transaction) -> Vec<(String, Param)> {
+    let mut fields: Vec<_> = actual
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| (format!("[{i}]"), param.clone()))
+        .collect();
+    if let Some(response) = &actual.response {
+        fields.push((String::from("response:"), Param::Text(response.clone())));
+    }
+    if let Some(outcome) = &actual.outcome {
+        fields.push((
+            String::from("outcome:"),
+            Param::Text(format!("{outcome:#?}")),
+        ));
+    }
+    fields
+}
+
+fn mismatched_lines(
+    expected: &ExpectedTx,
+    actual: &Transaction,
+    fields: &[FieldDiff],
+    lines: &mut Vec<Line>,
+) {
+    let differs: BTreeSet<usize> = fields
+        .iter()
+        .filter_map(|diff| match diff {
+            FieldDiff::Param { index, .. }
+            | FieldDiff::MissingParam { index, .. }
+            | FieldDiff::ExtraParam { index, .. } => Some(*index),
+            FieldDiff::Kind { .. }
+            | FieldDiff::ParamCount { .. }
+            | FieldDiff::Response { .. }
+            | FieldDiff::Outcome { .. } => None,
+        })
+        .collect();
+
+    if expected.kind == actual.kind {
+        lines.push(Line::Split(
+            cell(format!("kind: {}", expected.kind), Style::Plain),
+            cell(format!("kind: {}", actual.kind), Style::Plain),
+        ));
+    } else {
+        lines.push(Line::Split(
+            cell(format!("kind: {}", expected.kind), Style::Removed),
+            cell(format!("kind: {}", actual.kind), Style::Inserted),
+        ));
+    }
+
+    for i in 0..expected.params.len().max(actual.params.len()) {
+        field_lines(
+            &format!("[{i}]"),
+            expected.params.get(i),
+            actual.params.get(i),
+            differs.contains(&i),
+            lines,
+        );
+    }
+
+    let response_differs = fields
+        .iter()
+        .any(|diff| matches!(diff, FieldDiff::Response { .. }));
+    if expected.response.is_some() || actual.response.is_some() {
+        field_lines(
+            "response:",
+            expected.response.as_ref(),
+            actual.response.clone().map(Param::Text).as_ref(),
+            response_differs,
+            lines,
+        );
+    }
+
+    let outcome_differs = fields
+        .iter()
+        .any(|diff| matches!(diff, FieldDiff::Outcome { .. }));
+    if expected.outcome.is_some() || actual.outcome.is_some() {
+        field_lines(
+            "outcome:",
+            outcome_matcher(&expected.outcome).as_ref(),
+            actual
+                .outcome
+                .as_ref()
+                .map(|o| Param::Text(format!("{o:#?}")))
+                .as_ref(),
+            outcome_differs,
+            lines,
+        );
+    }
+}
+
+/// An expected outcome as text, so it can be diffed against an actual outcome's Debug output.
+fn outcome_matcher(outcome: &Option<ExpectedOutcome>) -> Option<ParamMatcher> {
+    outcome
+        .as_ref()
+        .map(|o| ParamMatcher::Literal(format!("{o:#?}")))
+}
+
+/// Rows for one param, the response or the outcome.
+fn field_lines(
+    label: &str,
+    expected: Option<&ParamMatcher>,
+    actual: Option<&Param>,
+    differs: bool,
+    lines: &mut Vec<Line>,
+) {
+    match (expected, actual) {
+        (Some(matcher), Some(param)) if !differs => {
+            lines.push(Line::Summary(
+                cell(format!("{label} {}", brief_matcher(matcher)), Style::Dim),
+                cell(format!("{label} {}", brief(param)), Style::Dim),
+            ));
+        }
+        (Some(ParamMatcher::Literal(old)), Some(new))
+        | (Some(ParamMatcher::Pattern(Pattern::Eq(old))), Some(new)) => {
+            diff_lines(label, old, &new.as_text(), lines);
+        }
+        (Some(ParamMatcher::Typed(old)), Some(new)) => {
+            if old.type_name() != new.type_name() {
+                lines.push(Line::Split(
+                    cell(format!("{label} type: {}", old.type_name()), Style::Removed),
+                    cell(
+                        format!("{label} type: {}", new.type_name()),
+                        Style::Inserted,
+                    ),
+                ));
+            }
+            diff_lines(label, &old.as_text(), &new.as_text(), lines);
+        }
+        (Some(matcher), Some(param)) => {
+            let left = matcher_cells(label, matcher, Style::Removed);
+            let right = param_cells(label, &param.as_text(), Style::Inserted);
+            pair_cells(left, right, lines);
+        }
+        (Some(matcher), None) => pair_cells(
+            matcher_cells(label, matcher, Style::Removed),
+            Vec::new(),
+            lines,
+        ),
+        (None, Some(param)) => pair_cells(
+            Vec::new(),
+            param_cells(label, &param.as_text(), Style::Inserted),
+            lines,
+        ),
+        (None, None) => {}
+    }
+}
+
+/// Put two runs of cells next to each other, row by row.
+fn pair_cells(left: Vec<Cell>, right: Vec<Cell>, lines: &mut Vec<Line>) {
+    let mut left = left.into_iter();
+    let mut right = right.into_iter();
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => break,
+            (l, r) => lines.push(Line::Split(l.unwrap_or_default(), r.unwrap_or_default())),
+        }
+    }
+}
+
+/// One cell per line of a param, the first labelled.
+fn param_cells(label: &str, param: &str, style: Style) -> Vec<Cell> {
+    let mut cells = Vec::new();
+    let mut param_lines = param.lines();
+    match param_lines.next() {
+        Some(first) => cells.push(cell(format!("{label} {first}"), style)),
+        None => cells.push(cell(format!("{label} "), style)),
+    }
+    for line in param_lines {
+        cells.push(cell(format!("    {line}"), style));
+    }
+    cells
+}
+
+fn matcher_cells(label: &str, matcher: &ParamMatcher, style: Style) -> Vec<Cell> {
+    match matcher {
+        ParamMatcher::Literal(s) => param_cells(label, s, style),
+        ParamMatcher::Typed(param) => param_cells(label, &param.as_text(), style),
+        ParamMatcher::Pattern(pattern) => vec![cell(format!("{label} {pattern:?}"), style)],
+    }
+}
+
+/// A param on one line: text quoted, other types with their type.
+fn brief(param: &Param) -> String {
+    match param {
+        Param::Text(s) => format!("{s:?}"),
+        param => format!("{param:?}"),
+    }
+}
+
+fn brief_matcher(matcher: &ParamMatcher) -> String {
+    match matcher {
+        ParamMatcher::Typed(param) => brief(param),
+        matcher => format!("{matcher:?}"),
+    }
+}
+
+/// A line-level diff of a param, side by side, with the differing characters of changed
+/// lines emphasized.
+fn diff_lines(label: &str, old: &str, new: &str, lines: &mut Vec<Line>) {
+    let diff = TextDiff::from_lines(old, new);
+    let old_lines: Vec<&str> = diff
+        .old_slices()
+        .iter()
+        .map(|l| l.trim_end_matches('\n'))
+        .collect();
+    let new_lines: Vec<&str> = diff
+        .new_slices()
+        .iter()
+        .map(|l| l.trim_end_matches('\n'))
+        .collect();
+
+    if old_lines.len() <= 1 && new_lines.len() <= 1 {
+        let (mut left, mut right) = char_diff(
+            old_lines.first().unwrap_or(&""),
+            new_lines.first().unwrap_or(&""),
+        );
+        left.insert(0, (format!("{label} "), Style::Plain));
+        right.insert(0, (format!("{label} "), Style::Plain));
+        lines.push(Line::Split(left, right));
+        return;
+    }
+
+    lines.push(Line::Split(
+        cell(label, Style::Plain),
+        cell(label, Style::Plain),
+    ));
+    let indent = |mut cell: Cell| {
+        cell.insert(0, ("    ".to_string(), Style::Plain));
+        cell
+    };
+    for op in diff.ops() {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => {
+                for line in &old_lines[old_range] {
+                    lines.push(Line::Split(
+                        indent(cell(*line, Style::Plain)),
+                        indent(cell(*line, Style::Plain)),
+                    ));
+                }
+            }
+            DiffTag::Delete => {
+                for line in &old_lines[old_range] {
+                    lines.push(Line::Split(indent(cell(*line, Style::Removed)), Vec::new()));
+                }
+            }
+            DiffTag::Insert => {
+                for line in &new_lines[new_range] {
+                    lines.push(Line::Split(
+                        Vec::new(),
+                        indent(cell(*line, Style::Inserted)),
+                    ));
+                }
+            }
+            DiffTag::Replace => {
+                let old_block = &old_lines[old_range];
+                let new_block = &new_lines[new_range];
+                for k in 0..old_block.len().max(new_block.len()) {
+                    let (left, right) = match (old_block.get(k), new_block.get(k)) {
+                        (Some(a), Some(b)) => char_diff(a, b),
+                        (Some(a), None) => (cell(*a, Style::Removed), Vec::new()),
+                        (None, Some(b)) => (Vec::new(), cell(*b, Style::Inserted)),
+                        (None, None) => unreachable!(),
+                    };
+                    lines.push(Line::Split(indent(left), indent(right)));
+                }
+            }
+        }
+    }
+}
+
+fn char_diff(old: &str, new: &str) -> (Cell, Cell) {
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    for change in TextDiff::from_chars(old, new).iter_all_changes() {
+        let value = change.value().to_string();
+        match change.tag() {
+            ChangeTag::Equal => {
+                left.push((value.clone(), Style::Removed));
+                right.push((value, Style::Inserted));
+            }
+            ChangeTag::Delete => left.push((value, Style::RemovedEmph)),
+            ChangeTag::Insert => right.push((value, Style::InsertedEmph)),
+        }
+    }
+    (left, right)
+}
+
+fn summary(kind: &str, params: impl Iterator<Item = String>) -> String {
+    format!("{kind}({})", params.collect::<Vec<_>>().join(", "))
+}
+
+fn layout(lines: &[Line], options: &RenderOptions, out: &mut String) {
+    let column = options.column();
+    let blank = " ".repeat(column);
+
+    for line in lines {
+        let (left, right) = match line {
+            Line::Full(text) => {
+                let _ = writeln!(out, "{text}");
+                continue;
+            }
+            Line::Split(left, right) => (wrap(left, column), wrap(right, column)),
+            Line::Summary(left, right) => {
+                (vec![truncate(left, column)], vec![truncate(right, column)])
+            }
+        };
+
+        for k in 0..left.len().max(right.len()) {
+            let row = format!(
+                "{} {} {}",
+                left.get(k).unwrap_or(&blank),
+                "│".dimmed(),
+                right.get(k).unwrap_or(&blank)
+            );
+            let _ = writeln!(out, "{}", row.trim_end());
+        }
+    }
+}
+
+/// Break a cell into painted lines of exactly `width` characters.
+fn wrap(cell: &Cell, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut len = 0;
+
+    for (text, style) in cell {
+        let mut chunk = String::new();
+        for c in text.chars() {
+            if len == width {
+                line.push_str(&paint(&chunk, *style).to_string());
+                lines.push(std::mem::take(&mut line));
+                chunk.clear();
+                len = 0;
+            }
+            chunk.push(if c.is_control() { ' ' } else { c });
+            len += 1;
+        }
+        line.push_str(&paint(&chunk, *style).to_string());
+    }
+
+    if len > 0 || lines.is_empty() {
+        line.push_str(&" ".repeat(width - len));
+        lines.push(line);
+    }
+    lines
+}
+
+/// Cut a cell down to a single painted line of exactly `width` characters.
+fn truncate(cell: &Cell, width: usize) -> String {
+    let total: usize = cell.iter().map(|(text, _)| text.chars().count()).sum();
+    if total <= width {
+        return wrap(cell, width).remove(0);
+    }
+
+    let mut out = String::new();
+    let mut budget = width - 1;
+    let mut style = Style::Plain;
+    for (text, s) in cell {
+        let chunk: String = text
+            .chars()
+            .take(budget)
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        budget -= chunk.chars().count();
+        out.push_str(&paint(&chunk, *s).to_string());
+        style = *s;
+        if budget == 0 {
+            break;
+        }
+    }
+    out.push_str(&paint("…", style).to_string());
+    out
+}
@@ -0,0 +1,176 @@
+use serde::Serialize;
+
+use crate::{expect::ExpectedTx, metadata::Metadata, tx::Transaction, txdiff::FieldDiff};
+
+/// One aligned step of a comparison between a left side (expectations or a reference store)
+/// and a right side (a store).
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Entry {
+    Matched {
+        left_index: usize,
+        right_index: usize,
+        actual: Transaction,
+    },
+    Mismatched {
+        left_index: usize,
+        right_index: usize,
+        expected: ExpectedTx,
+        actual: Transaction,
+        /// Problems that aren't differences between fields, e.g. inconsistent captures.
+        notes: Vec<String>,
+        fields: Vec<FieldDiff>,
+    },
+    /// Expected on the left, absent on the right.
+    Missing {
+        left_index: usize,
+        expected: ExpectedTx,
+    },
+    /// Present on the right, not expected on the left.
+    Extra {
+        right_index: usize,
+        actual: Transaction,
+    },
+}
+
+impl Entry {
+    pub fn is_match(&self) -> bool {
+        matches!(self, Entry::Matched { .. })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonReport {
+    pub left: String,
+    pub right: String,
+    /// Why the right side is allowed to deviate from the shared expectations, if it is.
+    pub justification: Option<String>,
+    pub left_len: usize,
+    pub right_len: usize,
+    pub entries: Vec<Entry>,
+}
+
+impl ComparisonReport {
+    pub fn is_ok(&self) -> bool {
+        self.left_len == self.right_len && self.entries.iter().all(Entry::is_match)
+    }
+}
+
+/// A slot in a multi-store alignment: either one transaction of the center store, or the
+/// gap before it (or after the last one) where other stores may have inserted transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Slot {
+    Gap(usize),
+    Position(usize),
+}
+
+/// Stores that agree on the contents of a slot.
+#[derive(Debug, Clone, Serialize)]
+pub struct Faction {
+    pub stores: Vec<String>,
+    /// How this faction's view differs from the first faction's. Empty for the first faction.
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Disagreement {
+    pub slot: Slot,
+    /// The kind of the center store's transaction at this slot, if it has one.
+    pub kind: Option<String>,
+    pub has_majority: bool,
+    /// Largest first.
+    pub factions: Vec<Faction>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Culprit {
+    pub store: String,
+    /// Number of slots at which the store diverged from the majority.
+    pub divergences: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MultiStoreReport {
+    pub stores: Vec<String>,
+    /// The store all others were aligned against.
+    pub center: Option<String>,
+    pub disagreements: Vec<Disagreement>,
+    pub culprits: Vec<Culprit>,
+    /// Number of slots at which no view held a majority.
+    pub undecided: usize,
+}
+
+impl MultiStoreReport {
+    pub fn is_ok(&self) -> bool {
+        self.disagreements.is_empty()
+    }
+}
+
+/// Call latency of one kind in one store, in nanoseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct Latency {
+    pub store: String,
+    /// Number of timed calls.
+    pub count: usize,
+    pub p50: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KindLatency {
+    pub kind: String,
+    /// One per store with timed calls of this kind, in the order of the report's stores.
+    pub stores: Vec<Latency>,
+}
+
+/// A latency threshold a store exceeded.
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyViolation {
+    pub kind: String,
+    pub store: String,
+    pub message: String,
+}
+
+/// Per-kind latency distributions of every store, compared against the first.
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyReport {
+    pub stores: Vec<String>,
+    pub kinds: Vec<KindLatency>,
+    pub violations: Vec<LatencyViolation>,
+}
+
+impl LatencyReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// What produced a store, shown alongside mismatches so they can be reproduced.
+#[derive(Debug, Clone, Serialize)]
+pub struct StoreMetadata {
+    pub store: String,
+    pub metadata: Vec<Metadata>,
+}
+
+/// Any report produced while running or recording a sequence.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Report {
+    Comparison(ComparisonReport),
+    MultiStore(MultiStoreReport),
+    Latency(LatencyReport),
+    Metadata(StoreMetadata),
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        match self {
+            Report::Comparison(report) => report.is_ok(),
+            Report::MultiStore(report) => report.is_ok(),
+            Report::Latency(report) => report.is_ok(),
+            Report::Metadata(_) => true,
+        }
+    }
+}
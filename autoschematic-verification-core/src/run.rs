@@ -0,0 +1,255 @@
+use std::time::SystemTime;
+
+use anyhow::bail;
+use redb::{
+    ReadableDatabase, ReadableTable, ReadableTableMetadata, TableDefinition, TableError,
+    TableHandle,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{METADATA_TABLE, OUTPUT_TABLE, RUNS_TABLE, TABLE, schema};
+
+/// One run of a sequence against a store. Transactions and metadata are written to the
+/// tables of the store's latest run, as is the output of the sequence's commands; stores
+/// without any runs use the plain `transactions` and `metadata` tables.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunInfo {
+    pub id: String,
+    /// When the run began, in nanoseconds since the Unix epoch.
+    pub started: u64,
+}
+
+/// A run id from the current time, e.g. "run-1760781300123".
+pub fn new_id() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    format!("run-{}", since_epoch.as_millis())
+}
+
+pub(crate) fn transactions_table(run: Option<&str>) -> String {
+    match run {
+        Some(run) => format!("{}/{run}", TABLE.name()),
+        None => TABLE.name().to_string(),
+    }
+}
+
+pub(crate) fn metadata_table(run: Option<&str>) -> String {
+    match run {
+        Some(run) => format!("{}/{run}", METADATA_TABLE.name()),
+        None => METADATA_TABLE.name().to_string(),
+    }
+}
+
+pub(crate) fn output_table(run: Option<&str>) -> String {
+    match run {
+        Some(run) => format!("{}/{run}", OUTPUT_TABLE.name()),
+        None => OUTPUT_TABLE.name().to_string(),
+    }
+}
+
+/// Start a new run in the store, creating it if needed. Writes go to this run from now on.
+pub fn begin(db: &redb::Database, id: &str) -> anyhow::Result<()> {
+    if list(db)?.iter().any(|run| run.id == id) {
+        bail!("Run {} already exists", id);
+    }
+
+    let write_txn = db.begin_write()?;
+    schema::prepare_write(&write_txn)?;
+
+    {
+        let mut table = write_txn.open_table(RUNS_TABLE)?;
+
+        let key = match table.last()? {
+            Some((last, _)) => last.value() + 1,
+            None => 0,
+        };
+
+        let run = RunInfo {
+            id: id.to_string(),
+            started: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64,
+        };
+        table.insert(key, &serde_json::to_string(&run)?)?;
+    }
+
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+/// Every run in the store, oldest first. Fails if the store's schema is newer than this build.
+pub fn list(db: &redb::Database) -> anyhow::Result<Vec<RunInfo>> {
+    schema::store_version(db)?.check_supported()?;
+
+    let read_txn = db.begin_read()?;
+    let table = match read_txn.open_table(RUNS_TABLE) {
+        Ok(table) => table,
+        Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut runs = Vec::new();
+    for row in table.iter()? {
+        runs.push(serde_json::from_str(&row?.1.value())?);
+    }
+
+    Ok(runs)
+}
+
+/// The run to read: `run` if given, which must exist, or else the latest run. `None` means
+/// the store has no runs.
+pub fn resolve(db: &redb::Database, run: Option<&str>) -> anyhow::Result<Option<String>> {
+    let runs = list(db)?;
+    match run {
+        Some(id) if runs.iter().any(|r| r.id == id) => Ok(Some(id.to_string())),
+        Some(id) => bail!("No run {} in store", id),
+        None => Ok(runs.last().map(|r| r.id.clone())),
+    }
+}
+
+/// The latest run, as seen from inside a write transaction.
+pub(crate) fn latest(write_txn: &redb::WriteTransaction) -> anyhow::Result<Option<String>> {
+    let table = write_txn.open_table(RUNS_TABLE)?;
+    match table.last()? {
+        Some((_, run)) => Ok(Some(serde_json::from_str::<RunInfo>(&run.value())?.id)),
+        None => Ok(None),
+    }
+}
+
+/// Number of transactions recorded in a run.
+pub fn len(db: &redb::Database, run: Option<&str>) -> anyhow::Result<u64> {
+    let read_txn = db.begin_read()?;
+    let name = transactions_table(run);
+    match read_txn.open_table(TableDefinition::<u128, String>::new(&name)) {
+        Ok(table) => Ok(table.len()?),
+        Err(TableError::TableDoesNotExist(_)) => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Delete all but the `keep` latest runs, returning the ids of those deleted.
+pub fn prune(db: &redb::Database, keep: usize) -> anyhow::Result<Vec<String>> {
+    let write_txn = db.begin_write()?;
+    schema::prepare_write(&write_txn)?;
+    let mut pruned = Vec::new();
+
+    {
+        let mut table = write_txn.open_table(RUNS_TABLE)?;
+
+        let mut keys = Vec::new();
+        for row in table.iter()? {
+            let (key, run) = row?;
+            keys.push((
+                key.value(),
+                serde_json::from_str::<RunInfo>(&run.value())?.id,
+            ));
+        }
+
+        for (key, id) in keys.iter().take(keys.len().saturating_sub(keep)) {
+            table.remove(key)?;
+            write_txn.delete_table(TableDefinition::<u128, String>::new(&transactions_table(
+                Some(id),
+            )))?;
+            write_txn.delete_table(TableDefinition::<u128, String>::new(&metadata_table(Some(
+                id,
+            ))))?;
+            write_txn.delete_table(TableDefinition::<u128, String>::new(&output_table(Some(
+                id,
+            ))))?;
+            pruned.push(id.clone());
+        }
+    }
+
+    write_txn.commit()?;
+
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metadata::Metadata,
+        testutil::{TempDir, tx},
+        tx::Transaction,
+    };
+
+    fn ids(db: &redb::Database) -> Vec<String> {
+        list(db).unwrap().into_iter().map(|r| r.id).collect()
+    }
+
+    fn tables(db: &redb::Database) -> Vec<String> {
+        let read_txn = db.begin_read().unwrap();
+        let mut names: Vec<String> = read_txn
+            .list_tables()
+            .unwrap()
+            .map(|t| t.name().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn new_ids_are_timestamps() {
+        let id = new_id();
+        let millis = id.strip_prefix("run-").unwrap();
+        assert!(millis.parse::<u128>().unwrap() > 0, "{id}");
+    }
+
+    #[test]
+    fn writes_go_to_the_latest_run() {
+        let dir = TempDir::new();
+        let db = dir.db("store.redb");
+        assert_eq!(resolve(&db, None).unwrap(), None);
+
+        Transaction::write_all(&db, &[tx("get", "before runs")]).unwrap();
+        begin(&db, "first").unwrap();
+        Transaction::write_all(&db, &[tx("get", "a"), tx("get", "b")]).unwrap();
+        begin(&db, "second").unwrap();
+        Transaction::write_all(&db, &[tx("get", "c")]).unwrap();
+
+        assert_eq!(ids(&db), ["first", "second"]);
+        assert!(list(&db).unwrap()[0].started <= list(&db).unwrap()[1].started);
+        assert_eq!(len(&db, None).unwrap(), 1);
+        assert_eq!(len(&db, Some("first")).unwrap(), 2);
+        assert_eq!(len(&db, Some("second")).unwrap(), 1);
+        assert_eq!(len(&db, Some("missing")).unwrap(), 0);
+
+        assert_eq!(resolve(&db, None).unwrap().as_deref(), Some("second"));
+        assert_eq!(
+            resolve(&db, Some("first")).unwrap().as_deref(),
+            Some("first")
+        );
+        let e = resolve(&db, Some("third")).unwrap_err();
+        assert_eq!(e.to_string(), "No run third in store");
+
+        let e = begin(&db, "first").unwrap_err();
+        assert_eq!(e.to_string(), "Run first already exists");
+    }
+
+    #[test]
+    fn prune_deletes_old_runs_and_their_tables() {
+        let dir = TempDir::new();
+        let db = dir.db("store.redb");
+        for id in ["first", "second", "third"] {
+            begin(&db, id).unwrap();
+            Transaction::write_all(&db, &[tx("get", id)]).unwrap();
+            Metadata::default().write(&db).unwrap();
+        }
+
+        assert_eq!(prune(&db, 5).unwrap(), Vec::<String>::new());
+        assert_eq!(prune(&db, 1).unwrap(), ["first", "second"]);
+        assert_eq!(ids(&db), ["third"]);
+        assert_eq!(
+            tables(&db),
+            ["metadata/third", "runs", "schema", "transactions/third"]
+        );
+
+        assert_eq!(prune(&db, 0).unwrap(), ["third"]);
+        assert!(ids(&db).is_empty());
+        assert_eq!(resolve(&db, None).unwrap(), None);
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::bail;
+use redb::{ReadableDatabase, ReadableTable, TableDefinition, TableError, TableHandle};
+use serde::{Deserialize, Serialize};
+
+use crate::{TABLE, tx::Transaction};
+
+const SCHEMA_TABLE: TableDefinition<&str, u64> = TableDefinition::new("schema");
+
+/// Version of the on-disk format of stores and sequence files.
+///
+/// 1. Unversioned: a single `transactions` table, params recorded as plain strings.
+/// 2. Typed params, runs, metadata and timing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct SchemaVersion(pub u64);
+
+impl SchemaVersion {
+    pub const UNVERSIONED: SchemaVersion = SchemaVersion(1);
+    pub const CURRENT: SchemaVersion = SchemaVersion(2);
+
+    /// The version of files written before versions were recorded.
+    pub fn unversioned() -> SchemaVersion {
+        SchemaVersion::UNVERSIONED
+    }
+
+    /// Fail if this build can't read this version.
+    pub fn check_supported(self) -> anyhow::Result<()> {
+        if self > SchemaVersion::CURRENT {
+            bail!(
+                "Schema version {} is newer than this build supports ({})",
+                self,
+                SchemaVersion::CURRENT
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Default for SchemaVersion {
+    fn default() -> Self {
+        SchemaVersion::CURRENT
+    }
+}
+
+impl std::fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The store's schema version. Stores without one are unversioned, unless they're empty.
+pub fn store_version(db: &redb::Database) -> anyhow::Result<SchemaVersion> {
+    let read_txn = db.begin_read()?;
+    let table = match read_txn.open_table(SCHEMA_TABLE) {
+        Ok(table) => Some(table),
+        Err(TableError::TableDoesNotExist(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    match table.map(|t| t.get("version")).transpose()?.flatten() {
+        Some(version) => Ok(SchemaVersion(version.value())),
+        None if read_txn.list_tables()?.next().is_some() => Ok(SchemaVersion::UNVERSIONED),
+        None => Ok(SchemaVersion::CURRENT),
+    }
+}
+
+/// Called by every writer: stamps the current version on a new store, and refuses to write
+/// to a store of any other version.
+pub(crate) fn prepare_write(write_txn: &redb::WriteTransaction) -> anyhow::Result<()> {
+    let has_data = write_txn
+        .list_tables()?
+        .any(|table| table.name() != SCHEMA_TABLE.name());
+    let mut table = write_txn.open_table(SCHEMA_TABLE)?;
+
+    let stored = table.get("version")?.map(|v| SchemaVersion(v.value()));
+    let version = match stored {
+        Some(version) => version,
+        None if has_data => SchemaVersion::UNVERSIONED,
+        None => {
+            table.insert("version", SchemaVersion::CURRENT.0)?;
+            SchemaVersion::CURRENT
+        }
+    };
+
+    version.check_supported()?;
+    if version < SchemaVersion::CURRENT {
+        bail!(
+            "Store is at schema version {}, but writes need version {}; run `autoschematic-testbench migrate` on it",
+            version,
+            SchemaVersion::CURRENT
+        );
+    }
+    Ok(())
+}
+
+/// Upgrade a store to the current schema version in place, returning the versions it went
+/// through.
+pub fn migrate_store(db: &redb::Database) -> anyhow::Result<Vec<SchemaVersion>> {
+    let mut version = store_version(db)?;
+    version.check_supported()?;
+
+    let mut steps = Vec::new();
+    while version < SchemaVersion::CURRENT {
+        let write_txn = db.begin_write()?;
+        match version.0 {
+            1 => retype_params(&write_txn)?,
+            _ => unreachable!(),
+        }
+        version = SchemaVersion(version.0 + 1);
+        write_txn
+            .open_table(SCHEMA_TABLE)?
+            .insert("version", version.0)?;
+        write_txn.commit()?;
+        steps.push(version);
+    }
+
+    Ok(steps)
+}
+
+/// 1 to 2: rewrite every transaction so its params are tagged with their type. Untyped params
+/// become `Text`.
+fn retype_params(write_txn: &redb::WriteTransaction) -> anyhow::Result<()> {
+    let names: Vec<String> = write_txn
+        .list_tables()?
+        .map(|table| table.name().to_string())
+        .filter(|name| name == TABLE.name() || name.starts_with(&format!("{}/", TABLE.name())))
+        .collect();
+
+    for name in names {
+        let mut table = write_txn.open_table(TableDefinition::<u128, String>::new(&name))?;
+        let mut rows = Vec::new();
+        for row in table.iter()? {
+            let (key, tx) = row?;
+            let tx: Transaction = serde_json::from_str(&tx.value())?;
+            rows.push((key.value(), serde_json::to_string(&tx)?));
+        }
+        for (key, tx) in rows {
+            table.insert(key, &tx)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        param::Param,
+        testutil::{TempDir, tx, write_unversioned},
+    };
+
+    #[test]
+    fn migrate_retypes_plain_string_params() {
+        let dir = TempDir::new();
+        let db = dir.db("old.redb");
+        write_unversioned(
+            &db,
+            &[
+                r#"{"kind":"get","params":["scoreboard/state.ron"]}"#,
+                r#"{"kind":"plan","params":["scoreboard/state.ron","","(random_int: 1)"]}"#,
+            ],
+        );
+        assert_eq!(store_version(&db).unwrap(), SchemaVersion::UNVERSIONED);
+
+        assert_eq!(migrate_store(&db).unwrap(), [SchemaVersion::CURRENT]);
+        assert_eq!(store_version(&db).unwrap(), SchemaVersion::CURRENT);
+
+        // Rows are now stored tagged, not just read that way.
+        let read_txn = db.begin_read().unwrap();
+        let table = read_txn.open_table(TABLE).unwrap();
+        let row = table.get(1).unwrap().unwrap().value();
+        assert!(row.contains(r#"{"Text":"(random_int: 1)"}"#), "{row}");
+
+        let txs = Transaction::read_run(&db, None).unwrap();
+        assert_eq!(txs[0].params, [Param::Text("scoreboard/state.ron".into())]);
+        assert_eq!(
+            txs[1].params,
+            [
+                Param::Text("scoreboard/state.ron".into()),
+                Param::Text("".into()),
+                Param::Text("(random_int: 1)".into()),
+            ]
+        );
+
+        // A migrated store takes writes, and migrating again does nothing.
+        Transaction::write_all(&db, &[tx("list", "scoreboard")]).unwrap();
+        assert!(migrate_store(&db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unversioned_stores_refuse_writes() {
+        let dir = TempDir::new();
+        let db = dir.db("old.redb");
+        write_unversioned(&db, &[r#"{"kind":"get","params":["a"]}"#]);
+
+        let e = format!(
+            "{:#}",
+            Transaction::write_all(&db, &[tx("get", "b")]).unwrap_err()
+        );
+        assert!(e.contains("schema version 1"), "{e}");
+        assert!(e.contains("migrate"), "{e}");
+        assert_eq!(Transaction::read_run(&db, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn new_stores_are_stamped_current() {
+        let dir = TempDir::new();
+        let db = dir.db("new.redb");
+        Transaction::write_all(&db, &[tx("get", "a")]).unwrap();
+        let read_txn = db.begin_read().unwrap();
+        let version = read_txn
+            .open_table(SCHEMA_TABLE)
+            .unwrap()
+            .get("version")
+            .unwrap();
+        assert_eq!(version.map(|v| v.value()), Some(SchemaVersion::CURRENT.0));
+    }
+
+    #[test]
+    fn newer_stores_are_refused() {
+        let dir = TempDir::new();
+        let db = dir.db("new.redb");
+        let write_txn = db.begin_write().unwrap();
+        write_txn
+            .open_table(SCHEMA_TABLE)
+            .unwrap()
+            .insert("version", SchemaVersion::CURRENT.0 + 1)
+            .unwrap();
+        write_txn.commit().unwrap();
+
+        assert!(migrate_store(&db).is_err());
+        assert!(Transaction::write_all(&db, &[tx("get", "a")]).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+
+/// A RON document reduced to what matters for equality: its value, plus the struct and
+/// variant names that `ron::Value` throws away.
+#[derive(Debug, PartialEq)]
+struct RonShape {
+    value: ron::Value,
+    names: Names,
+}
+
+fn parse(s: &str) -> Option<RonShape> {
+    let value: ron::Value = ron::from_str(s).ok()?;
+    Some(RonShape {
+        value,
+        names: names(s)?,
+    })
+}
+
+/// Compare two params as RON values, so that formatting differences (whitespace, trailing
+/// commas, comments) are ignored. Params that do not both parse as RON must be equal strings.
+/// Struct fields and map entries may be in any order, as in `ron::Value`; struct and variant
+/// names are compared where they occur.
+pub fn ron_eq(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The names in a RON value, laid out like the value itself: struct fields by field name and
+/// map entries sorted, so that neither depends on their order in the source.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Names {
+    /// A string, char or number.
+    Literal,
+    /// A struct, tuple, variant, unit or keyword like `Some`/`None`, with its name if it has
+    /// one.
+    Node {
+        name: Option<String>,
+        children: Children,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Children {
+    None,
+    Ordered(Vec<Names>),
+    Fields(BTreeMap<String, Names>),
+    Entries(Vec<(Names, Names)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// An identifier, without the `r#` of a raw identifier.
+    Ident(String),
+    Literal,
+    Punct(char),
+}
+
+/// The names of a RON document, or `None` if it can't be read as one value.
+fn names(s: &str) -> Option<Names> {
+    let mut parser = Parser {
+        tokens: tokenize(s),
+        pos: 0,
+    };
+    parser.attributes()?;
+    let names = parser.value()?;
+    (parser.pos == parser.tokens.len()).then_some(names)
+}
+
+/// Split RON into identifiers, literals and punctuation, skipping whitespace, comments and
+/// signs.
+fn tokenize(s: &str) -> Vec<Token> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' {
+            i = skip_string(&chars, i + 1);
+            tokens.push(Token::Literal);
+        } else if c == '\'' {
+            i = skip_char(&chars, i + 1);
+            tokens.push(Token::Literal);
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if c == 'r' && chars.get(i + 1) == Some(&'#') && is_ident_start(chars.get(i + 2)) {
+            let (ident, end) = ident(&chars, i + 2);
+            tokens.push(Token::Ident(ident));
+            i = end;
+        } else if c == 'r' && matches!(chars.get(i + 1), Some('"') | Some('#')) {
+            i = skip_raw_string(&chars, i + 1);
+            tokens.push(Token::Literal);
+        } else if c == 'b' && matches!(chars.get(i + 1), Some('"') | Some('\'')) {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && is_digit(chars.get(i + 1))) {
+            let hex = c == '0' && matches!(chars.get(i + 1), Some('x') | Some('X'));
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                // The sign of an exponent.
+                if !hex
+                    && matches!(chars[i], 'e' | 'E')
+                    && matches!(chars.get(i + 1), Some('+' | '-'))
+                {
+                    i += 1;
+                }
+                i += 1;
+            }
+            tokens.push(Token::Literal);
+        } else if is_ident_start(Some(&c)) {
+            let (ident, end) = ident(&chars, i);
+            tokens.push(Token::Ident(ident));
+            i = end;
+        } else {
+            if !c.is_whitespace() && c != '+' && c != '-' {
+                tokens.push(Token::Punct(c));
+            }
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+fn is_ident_start(c: Option<&char>) -> bool {
+    c.is_some_and(|c| c.is_alphabetic() || *c == '_')
+}
+
+fn is_digit(c: Option<&char>) -> bool {
+    c.is_some_and(char::is_ascii_digit)
+}
+
+fn ident(chars: &[char], start: usize) -> (String, usize) {
+    let mut end = start;
+    while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+        end += 1;
+    }
+    (chars[start..end].iter().collect(), end)
+}
+
+fn skip_string(chars: &[char], mut i: usize) -> usize {
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+fn skip_char(chars: &[char], mut i: usize) -> usize {
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '\'' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+fn skip_raw_string(chars: &[char], mut i: usize) -> usize {
+    let mut hashes = 0;
+    while chars.get(i) == Some(&'#') {
+        hashes += 1;
+        i += 1;
+    }
+    // Skip the opening quote.
+    i += 1;
+    while i < chars.len() {
+        if chars[i] == '"'
+            && chars[i + 1..]
+                .iter()
+                .take(hashes)
+                .filter(|c| **c == '#')
+                .count()
+                == hashes
+        {
+            return i + 1 + hashes;
+        }
+        i += 1;
+    }
+    i
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self, ahead: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + ahead)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek(0) == Some(&Token::Punct(c));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.eat(c).then_some(())
+    }
+
+    /// Skip `#![enable(...)]` attributes, which don't affect the value.
+    fn attributes(&mut self) -> Option<()> {
+        while self.eat('#') {
+            self.eat('!');
+            self.expect('[')?;
+            let mut depth = 1;
+            while depth > 0 {
+                match self.next()? {
+                    Token::Punct('[') => depth += 1,
+                    Token::Punct(']') => depth -= 1,
+                    _ => {}
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn value(&mut self) -> Option<Names> {
+        match self.next()? {
+            Token::Literal => Some(Names::Literal),
+            Token::Ident(name) => {
+                let children = if self.eat('(') {
+                    self.parens()?
+                } else {
+                    Children::None
+                };
+                Some(Names::Node {
+                    name: Some(name),
+                    children,
+                })
+            }
+            Token::Punct('(') => Some(Names::Node {
+                name: None,
+                children: self.parens()?,
+            }),
+            Token::Punct('[') => Some(Names::Node {
+                name: None,
+                children: Children::Ordered(self.list(']')?),
+            }),
+            Token::Punct('{') => {
+                let mut entries = Vec::new();
+                while !self.eat('}') {
+                    let key = self.value()?;
+                    self.expect(':')?;
+                    entries.push((key, self.value()?));
+                    if !self.eat(',') {
+                        self.expect('}')?;
+                        break;
+                    }
+                }
+                entries.sort();
+                Some(Names::Node {
+                    name: None,
+                    children: Children::Entries(entries),
+                })
+            }
+            Token::Punct(_) => None,
+        }
+    }
+
+    /// The contents of a struct or tuple, after its opening parenthesis.
+    fn parens(&mut self) -> Option<Children> {
+        let is_struct = matches!(self.peek(0), Some(Token::Ident(_)))
+            && self.peek(1) == Some(&Token::Punct(':'));
+        if !is_struct {
+            return Some(Children::Ordered(self.list(')')?));
+        }
+
+        let mut fields = BTreeMap::new();
+        while !self.eat(')') {
+            let Token::Ident(field) = self.next()? else {
+                return None;
+            };
+            self.expect(':')?;
+            fields.insert(field, self.value()?);
+            if !self.eat(',') {
+                self.expect(')')?;
+                break;
+            }
+        }
+        Some(Children::Fields(fields))
+    }
+
+    /// Comma-separated values up to and including `close`.
+    fn list(&mut self, close: char) -> Option<Vec<Names>> {
+        let mut values = Vec::new();
+        while !self.eat(close) {
+            values.push(self.value()?);
+            if !self.eat(',') {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Some(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting_is_ignored() {
+        assert!(ron_eq(
+            "State(a: 1, b: [1, 2])",
+            "State(\n    a: 1, // one\n    b: [1, 2,],\n)"
+        ));
+    }
+
+    #[test]
+    fn field_order_is_ignored_at_every_level() {
+        assert!(ron_eq("A(x: 1, y: 2)", "A(y: 2, x: 1)"));
+        assert!(ron_eq(
+            "A(x: B(p: 1, q: Some(C)), y: D(1))",
+            "A(y: D(1), x: B(q: Some(C), p: 1))"
+        ));
+        assert!(ron_eq("{\"a\": E(1), \"b\": F}", "{\"b\": F, \"a\": E(1)}"));
+    }
+
+    #[test]
+    fn nested_names_are_compared_in_place() {
+        assert!(!ron_eq("A(x: B(1))", "A(x: C(1))"));
+        assert!(!ron_eq("A(x: B, y: C)", "A(x: C, y: B)"));
+        assert!(!ron_eq("[Some(B)]", "[Some(C)]"));
+        assert!(!ron_eq("A(x: 1)", "(x: 1)"));
+    }
+
+    #[test]
+    fn identifiers_in_strings_are_not_names() {
+        assert!(ron_eq("A(s: \"B(c: D)\")", "A(s: \"B(c: D)\" )"));
+        assert!(!ron_eq("A(s: \"B\")", "A(s: \"C\")"));
+        assert!(ron_eq(
+            "A(s: r#\"x \" Y\"#, t: 'Z')",
+            "A(t: 'Z', s: r#\"x \" Y\"#)"
+        ));
+    }
+
+    #[test]
+    fn raw_identifiers_match_plain_ones() {
+        assert_eq!(names("r#A(r#type: 1)"), names("A(type: 1)"));
+        assert!(ron_eq("A(r#type: B)", "A(type: B)"));
+    }
+
+    #[test]
+    fn numbers_are_literals() {
+        assert_eq!(names("[1e-5, -2, 0x1e, 1.5E+3]"), names("[1, 2, 3, 4]"));
+    }
+}
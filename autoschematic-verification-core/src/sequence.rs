@@ -1,84 +1,262 @@
-use std::{path::PathBuf, process::Command};
+use std::{borrow::Cow, collections::BTreeMap, time::Duration};
 
-use anyhow::Context;
-use itertools::Itertools;
-use redb::{ReadableDatabase, ReadableTable};
+use anyhow::{Context, bail};
 use serde::{Deserialize, Serialize};
 
-use crate::{TABLE, crosscheck, tx::Transaction};
+use crate::{
+    crosscheck::{self, CompareOptions},
+    expect::{Expectation, StoreOverride},
+    latency::{self, LatencyThresholds},
+    metadata::Metadata,
+    normalize::Normalizer,
+    nway,
+    output::{SnapshotMode, StepOutput},
+    report::{ComparisonReport, Report, StoreMetadata},
+    run,
+    schema::{self, SchemaVersion},
+    step::{self, Step},
+};
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Sequence {
-    commands: Vec<Vec<String>>,
+    /// Sequence files without a version predate versioning.
+    #[serde(default = "SchemaVersion::unversioned")]
+    version: SchemaVersion,
+    commands: Vec<Step>,
+    /// Seconds any step without a timeout of its own may run before it's killed. Unlimited if
+    /// unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    step_timeout_secs: Option<u64>,
     tx_stores: Vec<String>,
-    expected_txs: Vec<Transaction>,
+    expected_txs: Vec<Expectation>,
+    #[serde(default, skip_serializing_if = "CompareOptions::is_default")]
+    compare: CompareOptions,
+    /// Per-store deviations from `expected_txs`, keyed by tx_store.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    overrides: BTreeMap<String, StoreOverride>,
+    /// Latency limits checked on every run. Timing is never part of the comparison itself.
+    #[serde(default, skip_serializing_if = "LatencyThresholds::is_unset")]
+    latency: LatencyThresholds,
+    /// How many runs each tx_store keeps, the new one included. Unlimited if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keep_runs: Option<usize>,
+    /// `compare` with normalizers from outside the sequence file, if any were added.
+    #[serde(skip)]
+    effective_compare: Option<CompareOptions>,
 }
 
 impl Sequence {
-    pub fn run(&self) -> anyhow::Result<()> {
-        for tx_store in &self.tx_stores {
-            let tx_store = PathBuf::from(tx_store);
+    /// Apply `normalizers` before the sequence's own, e.g. ones shared by every sequence. They
+    /// aren't written back when recording.
+    pub fn add_normalizers(&mut self, normalizers: Vec<Normalizer>) -> anyhow::Result<()> {
+        let mut compare = self.compare.clone();
+        compare.normalize = self.compare_options().normalize.after(normalizers)?;
+        self.effective_compare = Some(compare);
+        Ok(())
+    }
+
+    /// The options the sequence compares with, normalizers added by `add_normalizers`
+    /// included.
+    pub fn compare_options(&self) -> &CompareOptions {
+        self.effective_compare.as_ref().unwrap_or(&self.compare)
+    }
 
-            if tx_store.is_file() {
-                std::fs::remove_file(&tx_store).context(format!("rm {}", tx_store.display()))?;
+    /// Check the sequence can be run: its version is supported and its overrides are sound.
+    fn validate(&self) -> anyhow::Result<()> {
+        self.version.check_supported()?;
+        self.check_overrides()
+    }
+
+    fn check_overrides(&self) -> anyhow::Result<()> {
+        for store in self.overrides.keys() {
+            if !self.tx_stores.contains(store) {
+                bail!("Override for {} does not name one of the tx_stores", store);
             }
         }
+        Ok(())
+    }
 
-        for command in &self.commands {
-            let cmd = command.first().unwrap();
-            let args = command.into_iter().skip(1);
-
-            Command::new(cmd)
-                .args(args)
-                .status()
-                .context(format!("Running command {}", cmd))?;
+    /// The expectations for one store: the shared `expected_txs`, patched by its override if any.
+    fn expected_for(&self, tx_store: &str) -> anyhow::Result<Cow<'_, [Expectation]>> {
+        match self.overrides.get(tx_store) {
+            Some(store_override) => {
+                let expected = store_override
+                    .apply(&self.expected_txs)
+                    .context(format!("applying override for {}", tx_store))?;
+                Ok(Cow::Owned(expected))
+            }
+            None => Ok(Cow::Borrowed(&self.expected_txs)),
         }
+    }
+
+    /// Compare a run of one store, or its latest run, against its expectations. The report
+    /// carries the store's override justification, if it has one.
+    fn check_store(&self, tx_store: &str, run: Option<&str>) -> anyhow::Result<ComparisonReport> {
+        let expected = self.expected_for(tx_store)?;
+        let db = redb::Database::open(tx_store).context(format!("open db {}", tx_store))?;
+        let mut report = crosscheck::compare_with_vec_report(
+            &expected,
+            (tx_store, &db),
+            run,
+            self.compare_options(),
+        )?;
+        report.justification = self
+            .overrides
+            .get(tx_store)
+            .map(|o| o.justification.clone());
+        Ok(report)
+    }
+
+    /// Metadata of a run of every tx_store, to show alongside a mismatch.
+    fn metadata_reports(&self, run: Option<&str>) -> anyhow::Result<Vec<Report>> {
+        self.tx_stores
+            .iter()
+            .map(|tx_store| {
+                let db = redb::Database::open(tx_store).context(format!("open db {}", tx_store))?;
+                Ok(Report::Metadata(StoreMetadata {
+                    store: tx_store.clone(),
+                    metadata: Metadata::read_run(&db, run)?,
+                }))
+            })
+            .collect()
+    }
+
+    /// Begin a run named `run_id` in every tx_store, pruning old runs beyond `keep_runs`, and
+    /// run the commands, storing their output with the run. Fails at the first command that
+    /// doesn't exit or print as expected.
+    fn start(&self, run_id: &str, snapshots: SnapshotMode) -> anyhow::Result<()> {
+        self.validate()?;
 
         for tx_store in &self.tx_stores {
-            let db1 = redb::Database::open(tx_store).context(format!("open db {}", tx_store))?;
-            crosscheck::compare_with_vec(db1, &self.expected_txs, false)?;
+            let db = redb::Database::create(tx_store).context(format!("create db {}", tx_store))?;
+            run::begin(&db, run_id).context(format!("begin run in {}", tx_store))?;
+            if let Some(keep) = self.keep_runs {
+                run::prune(&db, keep).context(format!("prune runs in {}", tx_store))?;
+            }
         }
 
-        Ok(())
+        let mut outputs = Vec::new();
+        let result = step::run_steps(
+            &self.commands,
+            snapshots,
+            &self.compare_options().normalize,
+            self.step_timeout_secs.map(Duration::from_secs),
+            &mut outputs,
+        );
+
+        // Stored whether or not the steps succeeded, to look into failures after the fact. A
+        // failed step is still the error reported.
+        let stored = self.tx_stores.iter().try_for_each(|tx_store| {
+            let db = redb::Database::open(tx_store).context(format!("open db {}", tx_store))?;
+            StepOutput::write_run(&db, run_id, &outputs)
+                .context(format!("storing step output in {}", tx_store))
+        });
+
+        result?;
+        stored
     }
 
-    pub fn record(&mut self) -> anyhow::Result<()> {
-        for tx_store in &self.tx_stores {
-            let tx_store = PathBuf::from(tx_store);
+    /// Compare a run of every tx_store, or their latest runs, against the expectations,
+    /// returning one report per store. A latency report of all stores follows if `latency` is
+    /// set or the sequence has latency thresholds, and the stores' metadata if anything failed.
+    pub fn check(&self, run: Option<&str>, latency: bool) -> anyhow::Result<Vec<Report>> {
+        self.validate()?;
+
+        let mut reports = self
+            .tx_stores
+            .iter()
+            .map(|tx_store| Ok(Report::Comparison(self.check_store(tx_store, run)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
 
-            if tx_store.is_file() {
-                std::fs::remove_file(&tx_store).context(format!("rm {}", tx_store.display()))?;
+        if latency || self.latency.is_set() {
+            let mut stores = Vec::new();
+            for tx_store in &self.tx_stores {
+                let db = redb::Database::open(tx_store).context(format!("open db {}", tx_store))?;
+                stores.push((tx_store.as_str(), db));
             }
+            reports.push(Report::Latency(latency::latency_report(
+                &stores,
+                run,
+                &self.latency,
+                self.compare_options(),
+            )?));
         }
 
-        for command in &self.commands {
-            let cmd = command.first().unwrap();
-            let args = command.into_iter().skip(1);
-            
-            Command::new(cmd)
-                .args(args)
-                .status()
-                .context(format!("Running command {}", cmd))?;
+        if reports.iter().any(|r| !r.is_ok()) {
+            reports.extend(self.metadata_reports(run)?);
         }
 
-        for (store1, store2) in self.tx_stores.iter().tuple_windows() {
-            let db1 = redb::Database::open(store1).context(format!("open db {}", store1))?;
-            let db2 = redb::Database::open(store2).context(format!("open db {}", store1))?;
-            crosscheck::compare(db1, db2, false)?;
-        }
+        Ok(reports)
+    }
 
-        self.expected_txs = Vec::new();
-        let db_path = self.tx_stores.first().unwrap();
-        let db1 = redb::Database::open(db_path).context(format!("open db {}", db_path))?;
-        let read_txn1 = db1.begin_read()?;
-        let table1 = read_txn1.open_table(TABLE)?;
+    /// Upgrade the sequence and every existing tx_store to the current schema version. Plain
+    /// string params in `expected_txs` remain valid as literals, so only the version changes.
+    pub fn migrate(&mut self) -> anyhow::Result<()> {
+        self.version.check_supported()?;
 
-        for tx in table1.iter()? {
-            let tx = tx?.1.value();
-            let tx: Transaction = serde_json::from_str(&tx)?;
-            self.expected_txs.push(tx);
+        for tx_store in &self.tx_stores {
+            if !std::path::Path::new(tx_store).is_file() {
+                continue;
+            }
+            let db = redb::Database::open(tx_store).context(format!("open db {}", tx_store))?;
+            for version in schema::migrate_store(&db).context(format!("migrate {}", tx_store))? {
+                eprintln!("Migrated {} to schema version {}", tx_store, version);
+            }
         }
 
+        self.version = SchemaVersion::CURRENT;
         Ok(())
     }
+
+    /// Run the commands as run `run_id` and check it, as `check` does.
+    pub fn run(&self, run_id: &str, latency: bool) -> anyhow::Result<Vec<Report>> {
+        self.start(run_id, SnapshotMode::Check)?;
+        self.check(Some(run_id), latency)
+    }
+
+    /// Run the commands as run `run_id`, writing output snapshots, and record `expected_txs`
+    /// from the stores, once they all agree. `expected_txs` is left untouched if the returned
+    /// reports show a disagreement between the stores; stores with an override are checked
+    /// against the new recording after.
+    pub fn record(&mut self, run_id: &str) -> anyhow::Result<Vec<Report>> {
+        self.start(run_id, SnapshotMode::Update)?;
+
+        // Stores with an override are known to deviate, so they neither vote nor supply the
+        // recording; they are checked against the new recording, patched, instead.
+        let mut stores = Vec::new();
+        for tx_store in self.tx_stores.iter().filter(|s| !self.overrides.contains_key(*s)) {
+            let db = redb::Database::open(tx_store).context(format!("open db {}", tx_store))?;
+            stores.push((tx_store.as_str(), db));
+        }
+        let Some((db_path, db1)) = stores.first() else {
+            bail!("Every tx_store has an override, so there is nothing to record");
+        };
+        let agreement = nway::compare_stores_report(&stores, self.compare_options())?;
+        if !agreement.is_ok() {
+            drop(stores);
+            let mut reports = vec![Report::MultiStore(agreement)];
+            reports.extend(self.metadata_reports(Some(run_id))?);
+            return Ok(reports);
+        }
+
+        self.expected_txs = self
+            .compare_options()
+            .read_run_unprojected(db1, Some(run_id))?
+            .into_iter()
+            .map(Expectation::from)
+            .collect();
+        self.version = SchemaVersion::CURRENT;
+        eprintln!("Recorded expected_txs from {}", db_path);
+        drop(stores);
+
+        let mut reports = vec![Report::MultiStore(agreement)];
+        for tx_store in self.overrides.keys() {
+            reports.push(Report::Comparison(self.check_store(tx_store, Some(run_id))?));
+        }
+        if reports.iter().any(|r| !r.is_ok()) {
+            reports.extend(self.metadata_reports(Some(run_id))?);
+        }
+
+        Ok(reports)
+    }
 }
@@ -0,0 +1,592 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fixture::Fixture,
+    normalize::Normalizers,
+    output::{OutputMatcher, SnapshotMode, StepOutput},
+};
+
+/// One entry of a sequence's `commands`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Step {
+    /// A fixture, e.g. `Copy(from: "src/a.ron", to: "scoreboard/a.ron")`.
+    Fixture(Fixture),
+    /// A command line, e.g. `["autoschematic", "plan"]`, expected to exit with 0.
+    Args(Vec<String>),
+    Command(CommandStep),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandStep {
+    pub args: Vec<String>,
+    /// Variables to set, or to remove if `None`, in the command's environment.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, Option<String>>,
+    /// Start from an empty environment instead of the testbench's, before applying `env`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub clear_env: bool,
+    /// The directory to run the command in, if not the testbench's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// How long the command may run before it and every process it started are killed. Falls
+    /// back to the sequence's `step_timeout_secs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// The exit code the command must exit with.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub exit_code: i32,
+    /// Assertions on the command's stdout, which is captured rather than shown if there are
+    /// any. Output is stored with the run either way.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stdout: Vec<OutputMatcher>,
+    /// Assertions on the command's stderr, likewise.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stderr: Vec<OutputMatcher>,
+}
+
+fn is_zero(code: &i32) -> bool {
+    *code == 0
+}
+
+impl Step {
+    pub fn args(&self) -> &[String] {
+        match self {
+            Step::Fixture(_) => &[],
+            Step::Args(args) => args,
+            Step::Command(step) => &step.args,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Step::Fixture(_) | Step::Args(_) => 0,
+            Step::Command(step) => step.exit_code,
+        }
+    }
+
+    pub fn stdout(&self) -> &[OutputMatcher] {
+        match self {
+            Step::Fixture(_) | Step::Args(_) => &[],
+            Step::Command(step) => &step.stdout,
+        }
+    }
+
+    pub fn stderr(&self) -> &[OutputMatcher] {
+        match self {
+            Step::Fixture(_) | Step::Args(_) => &[],
+            Step::Command(step) => &step.stderr,
+        }
+    }
+
+    /// The step's own timeout, or `default`.
+    pub fn timeout(&self, default: Option<Duration>) -> Option<Duration> {
+        match self {
+            Step::Command(CommandStep {
+                timeout_secs: Some(secs),
+                ..
+            }) => Some(Duration::from_secs(*secs)),
+            _ => default,
+        }
+    }
+
+    /// The command line as it would be typed into a shell, or the fixture, for messages.
+    pub fn command_line(&self) -> String {
+        if let Step::Fixture(fixture) = self {
+            return fixture.to_string();
+        }
+        self.args()
+            .iter()
+            .map(|arg| {
+                if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"') {
+                    format!("{arg:?}")
+                } else {
+                    arg.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Run the steps in order, stopping at the first fixture that fails or command that can't be
+/// started, exits with anything but its expected exit code, outlives its timeout, or prints
+/// output its matchers reject. Steps without a timeout of their own use `default_timeout`.
+///
+/// Every command's output is collected into `outputs`, failing steps' included; streams
+/// without matchers are passed through as well. Output is normalized before it's matched; the
+/// error includes it as captured.
+pub fn run_steps(
+    steps: &[Step],
+    snapshots: SnapshotMode,
+    normalizers: &Normalizers,
+    default_timeout: Option<Duration>,
+    outputs: &mut Vec<StepOutput>,
+) -> anyhow::Result<()> {
+    for (i, step) in steps.iter().enumerate() {
+        if let Step::Fixture(fixture) = step {
+            fixture
+                .apply()
+                .context(format!("Step {} ({}) failed", i, fixture))?;
+            continue;
+        }
+
+        let timeout = step.timeout(default_timeout);
+        let execution = execute(i, step, timeout)?;
+        let output = StepOutput {
+            step: i,
+            command: step.command_line(),
+            exit_code: execution.status.and_then(|status| status.code()),
+            timed_out: execution.status.is_none(),
+            stdout: String::from_utf8_lossy(&execution.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&execution.stderr).into_owned(),
+        };
+        outputs.push(output.clone());
+
+        let streams = [
+            ("stdout", step.stdout(), output.stdout.as_str()),
+            ("stderr", step.stderr(), output.stderr.as_str()),
+        ];
+        let captured: String = streams
+            .iter()
+            .filter(|(_, matchers, _)| !matchers.is_empty())
+            .map(|(name, _, text)| format!("\n--- captured {name} ---\n{text}"))
+            .collect();
+
+        let Some(status) = execution.status else {
+            bail!(
+                "Step {} ({}) timed out after {}s; killed it and every process it started{}",
+                i,
+                step.command_line(),
+                timeout.unwrap_or_default().as_secs(),
+                captured
+            );
+        };
+
+        if status.code() != Some(step.exit_code()) {
+            bail!(
+                "Step {} ({}) failed: {}, expected exit code {}{}",
+                i,
+                step.command_line(),
+                status,
+                step.exit_code(),
+                captured
+            );
+        }
+
+        for (name, matchers, text) in &streams {
+            if matchers.is_empty() {
+                continue;
+            }
+            let text = normalizers.apply(text);
+            for matcher in matchers.iter() {
+                if let Err(e) = matcher.check(&text, snapshots) {
+                    bail!(
+                        "Step {} ({}) failed: {} {:#}{}",
+                        i,
+                        step.command_line(),
+                        name,
+                        e,
+                        captured
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What a command printed, and how it exited, or `None` if it timed out.
+struct Execution {
+    status: Option<ExitStatus>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+/// How long a command's output is still read after it exits or is killed, for processes it
+/// left behind that hold on to its pipes.
+const DRAIN_GRACE: Duration = Duration::from_secs(1);
+
+/// Run one step's command to completion. With a timeout, the command gets a process group of
+/// its own, which is killed as a whole if the timeout runs out or the testbench is interrupted
+/// while it runs.
+fn execute(i: usize, step: &Step, timeout: Option<Duration>) -> anyhow::Result<Execution> {
+    let Some((cmd, args)) = step.args().split_first() else {
+        bail!("Step {} has an empty command line", i);
+    };
+
+    let mut command = Command::new(cmd);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    if let Step::Command(step) = step {
+        if step.clear_env {
+            command.env_clear();
+        }
+        for (key, value) in &step.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        if let Some(cwd) = &step.cwd {
+            command.current_dir(cwd);
+        }
+    }
+
+    #[cfg(unix)]
+    if timeout.is_some() {
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    }
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut child =
+        command
+            .spawn()
+            .context(format!("Running step {}: {}", i, step.command_line()))?;
+    #[cfg(unix)]
+    let _guard = timeout
+        .is_some()
+        .then(|| interrupt::kill_on_interrupt(child.id()));
+
+    // Streams nobody asserts on are shown as they're printed, as if they weren't captured.
+    let stdout = read_to_end(
+        child.stdout.take().expect("stdout is piped"),
+        step.stdout().is_empty().then(std::io::stdout),
+    );
+    let stderr = read_to_end(
+        child.stderr.take().expect("stderr is piped"),
+        step.stderr().is_empty().then(std::io::stderr),
+    );
+
+    let status = match deadline {
+        Some(deadline) => wait_until(&mut child, deadline)?,
+        None => Some(child.wait()?),
+    };
+    if status.is_none() {
+        kill_tree(&mut child);
+        child.wait()?;
+    }
+
+    // Processes the command left behind may hold on to its pipes. They may go on until the
+    // deadline, after which they're killed too; without one, or once it has passed, the
+    // output is only waited for briefly, and what was read by then is kept.
+    let drain = deadline
+        .unwrap_or_else(Instant::now)
+        .max(Instant::now() + DRAIN_GRACE);
+    let drained = stdout.wait(drain) & stderr.wait(drain);
+    if !drained && status.is_some() && timeout.is_some() {
+        kill_tree(&mut child);
+        let grace = Instant::now() + DRAIN_GRACE;
+        stdout.wait(grace);
+        stderr.wait(grace);
+    }
+
+    Ok(Execution {
+        status,
+        stdout: stdout.output()?,
+        stderr: stderr.output()?,
+    })
+}
+
+/// A pipe being read on a thread of its own, so that neither stream fills up while the other
+/// is being read.
+struct Reader {
+    read: Arc<Mutex<Vec<u8>>>,
+    thread: JoinHandle<std::io::Result<()>>,
+}
+
+impl Reader {
+    /// Wait for the pipe to be closed, or until `until`. Returns whether it was.
+    fn wait(&self, until: Instant) -> bool {
+        while !self.thread.is_finished() {
+            let now = Instant::now();
+            if now >= until {
+                return false;
+            }
+            std::thread::sleep((until - now).min(Duration::from_millis(20)));
+        }
+        true
+    }
+
+    /// What was read, so far if the pipe is still open.
+    fn output(self) -> anyhow::Result<Vec<u8>> {
+        if self.thread.is_finished() {
+            self.thread.join().expect("output reader panicked")?;
+        }
+        Ok(std::mem::take(&mut *self.read.lock().unwrap()))
+    }
+}
+
+/// Start reading a pipe, passing what's read on to `echo` if given.
+fn read_to_end(
+    mut pipe: impl Read + Send + 'static,
+    mut echo: Option<impl Write + Send + 'static>,
+) -> Reader {
+    let read = Arc::new(Mutex::new(Vec::new()));
+    let buf = read.clone();
+    let thread = std::thread::spawn(move || {
+        let mut chunk = [0; 8192];
+        loop {
+            let n = match pipe.read(&mut chunk) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            buf.lock().unwrap().extend_from_slice(&chunk[..n]);
+            if let Some(echo) = &mut echo {
+                // The output is kept either way, so a closed terminal isn't an error.
+                let _ = echo.write_all(&chunk[..n]).and_then(|()| echo.flush());
+            }
+        }
+    });
+    Reader { read, thread }
+}
+
+/// Wait for the child to exit, or `None` if it's still running at `deadline`.
+fn wait_until(child: &mut Child, deadline: Instant) -> anyhow::Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep((deadline - now).min(Duration::from_millis(20)));
+    }
+}
+
+/// Kill the child's process group, which `execute` made it the leader of.
+#[cfg(unix)]
+fn kill_tree(child: &mut Child) {
+    // SAFETY: kill has no memory safety requirements; the group is the child's own.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_tree(child: &mut Child) {
+    let _ = child.kill();
+}
+
+/// A command in a process group of its own isn't in the terminal's foreground group, so
+/// Ctrl-C only reaches the testbench. While one runs, SIGINT and SIGTERM kill its group before
+/// the testbench exits on them as it would have anyway. The group is killed rather than sent
+/// the signal, as e.g. a shell's background jobs ignore SIGINT.
+#[cfg(unix)]
+mod interrupt {
+    use std::sync::{
+        Once,
+        atomic::{AtomicI32, Ordering},
+    };
+
+    /// The process group to kill, or 0 if none.
+    static GROUP: AtomicI32 = AtomicI32::new(0);
+
+    const SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+    /// Stops killing the group on interrupt when dropped.
+    pub(super) struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            GROUP.store(0, Ordering::SeqCst);
+        }
+    }
+
+    pub(super) fn kill_on_interrupt(group: u32) -> Guard {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            for signal in SIGNALS {
+                // SAFETY: the handler only makes async-signal-safe calls. A signal the
+                // testbench was started ignoring is left ignored.
+                unsafe {
+                    let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                    if libc::signal(signal, handler) == libc::SIG_IGN {
+                        libc::signal(signal, libc::SIG_IGN);
+                    }
+                }
+            }
+        });
+        GROUP.store(group as i32, Ordering::SeqCst);
+        Guard
+    }
+
+    extern "C" fn on_interrupt(signal: libc::c_int) {
+        let group = GROUP.load(Ordering::SeqCst);
+        // SAFETY: kill, signal and raise are async-signal-safe. The raised signal is
+        // delivered once the handler returns, with its default action.
+        unsafe {
+            if group > 0 {
+                libc::kill(-group, libc::SIGKILL);
+            }
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run steps written in RON, returning the result and what the commands printed.
+    fn run(steps: &str) -> (anyhow::Result<()>, Vec<StepOutput>) {
+        let steps: Vec<Step> = ron::from_str(steps).unwrap();
+        let mut outputs = Vec::new();
+        let result = run_steps(
+            &steps,
+            SnapshotMode::Check,
+            &Normalizers::default(),
+            None,
+            &mut outputs,
+        );
+        (result, outputs)
+    }
+
+    #[test]
+    fn steps_are_told_apart_by_shape() {
+        let steps: Vec<Step> =
+            ron::from_str(r#"[["true"], (args: ["false"], exit_code: 1), Remove("x")]"#).unwrap();
+        assert_eq!(steps[0], Step::Args(vec!["true".into()]));
+        let Step::Command(step) = &steps[1] else {
+            panic!("not a command: {:?}", steps[1]);
+        };
+        assert_eq!(
+            (step.args.as_slice(), step.exit_code),
+            (&["false".into()][..], 1)
+        );
+        assert_eq!(steps[2], Step::Fixture(Fixture::Remove("x".into())));
+    }
+
+    #[test]
+    fn exit_code_must_match() {
+        let (result, outputs) = run(r#"[["sh", "-c", "exit 3"]]"#);
+        let e = format!("{:#}", result.unwrap_err());
+        assert!(e.contains("expected exit code 0"), "{e}");
+        assert_eq!(outputs[0].exit_code, Some(3));
+
+        let (result, _) = run(r#"[(args: ["sh", "-c", "exit 3"], exit_code: 3)]"#);
+        result.unwrap();
+        let (result, _) = run(r#"[(args: ["true"], exit_code: 3)]"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn env_is_set_and_removed() {
+        let (result, outputs) = run(r#"[(
+            args: ["sh", "-c", "echo $X-${HOME-unset}"],
+            env: {"X": Some("1"), "HOME": None},
+        )]"#);
+        result.unwrap();
+        assert_eq!(outputs[0].stdout, "1-unset\n");
+    }
+
+    #[test]
+    fn clear_env_starts_empty() {
+        let (result, outputs) = run(r#"[(
+            args: ["/bin/sh", "-c", "echo ${HOME-unset}-$X"],
+            env: {"X": Some("1")},
+            clear_env: true,
+        )]"#);
+        result.unwrap();
+        assert_eq!(outputs[0].stdout, "unset-1\n");
+    }
+
+    #[test]
+    fn cwd_is_where_the_command_runs() {
+        let (result, outputs) = run(r#"[(args: ["pwd"], cwd: Some("/"))]"#);
+        result.unwrap();
+        assert_eq!(outputs[0].stdout, "/\n");
+    }
+
+    #[test]
+    fn output_is_kept_for_failing_steps() {
+        let (result, outputs) = run(r#"[
+            ["sh", "-c", "echo out; echo err >&2"],
+            ["sh", "-c", "echo failing >&2; exit 1"],
+            ["sh", "-c", "echo never run"],
+        ]"#);
+        assert!(result.is_err());
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(
+            (outputs[0].stdout.as_str(), outputs[0].stderr.as_str()),
+            ("out\n", "err\n")
+        );
+        assert_eq!(outputs[1].stderr, "failing\n");
+    }
+
+    /// Whether `pid` is a process that hasn't exited.
+    #[cfg(target_os = "linux")]
+    fn is_running(pid: &str) -> bool {
+        std::fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| {
+            !stat
+                .rsplit(')')
+                .next()
+                .unwrap()
+                .trim_start()
+                .starts_with('Z')
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn timeout_kills_the_process_group() {
+        let started = Instant::now();
+        let (result, outputs) = run(r#"[(
+            args: ["sh", "-c", "sleep 30 & echo $!; wait"],
+            timeout_secs: Some(1),
+        )]"#);
+        let e = format!("{:#}", result.unwrap_err());
+        assert!(e.contains("timed out after 1s"), "{e}");
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(outputs[0].timed_out);
+
+        // The background sleep was in the group, and its pid was printed before the timeout.
+        let pid = outputs[0].stdout.trim();
+        assert!(!pid.is_empty());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while is_running(pid) {
+            assert!(Instant::now() < deadline, "sleep {pid} outlived the step");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn lingering_processes_do_not_hold_up_the_step() {
+        // The background sleep keeps the pipes open after the shell exits.
+        let started = Instant::now();
+        let (result, outputs) = run(r#"[(
+            args: ["sh", "-c", "sleep 30 & echo started"],
+            timeout_secs: Some(2),
+        )]"#);
+        result.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(outputs[0].stdout, "started\n");
+        assert!(!outputs[0].timed_out);
+
+        let started = Instant::now();
+        let (result, outputs) = run(r#"[["sh", "-c", "sleep 5 & echo started"]]"#);
+        result.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert_eq!(outputs[0].stdout, "started\n");
+    }
+}
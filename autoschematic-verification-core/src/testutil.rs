@@ -0,0 +1,61 @@
+//! Fixtures shared by the unit tests.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{TABLE, param::Param, tx::Transaction};
+
+/// A transaction of `kind` with a single text param.
+pub fn tx(kind: &str, param: &str) -> Transaction {
+    Transaction {
+        kind: kind.into(),
+        params: vec![Param::Text(param.into())],
+        ..Default::default()
+    }
+}
+
+/// A directory of its own under the system's temporary directory, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "autoschematic-verification-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// A new, empty store in this directory.
+    pub fn db(&self, name: &str) -> redb::Database {
+        redb::Database::create(self.0.join(name)).unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Write rows as a store from before schema versions did: straight into the `transactions`
+/// table, with no version recorded.
+pub fn write_unversioned(db: &redb::Database, rows: &[&str]) {
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(TABLE).unwrap();
+        for (key, row) in (0..).zip(rows) {
+            table.insert(key, row.to_string()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+}
@@ -1,39 +1,171 @@
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 
 use diff::Diff;
+use redb::{ReadableDatabase, ReadableTable, TableDefinition, TableError};
 use serde::{Deserialize, Serialize};
 
-use crate::TABLE;
+use crate::{param::Param, run, schema};
 
-#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Diff)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Diff)]
 #[diff(attr(
     #[derive(Debug, PartialEq)]
 ))]
 pub struct Transaction {
     pub kind: String,
-    pub params: Vec<String>,
-    // #[serde(skip_serializing_if = "Option::is_none")]
-    // pub result: Option<String>,
+    pub params: Vec<Param>,
+    /// Wall-clock time of the write, in nanoseconds since the Unix epoch. Set by `write`, or
+    /// when queued on a `TxWriter`, and not part of equality: ordering comes from the store's
+    /// keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// The connector's response, as its Debug representation. Recorded on the connector's side
+    /// of the bridge, so it's what the connector answered, not necessarily what arrived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    /// Whether the call succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Outcome>,
+    /// How long the call took. Not part of equality.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<Timing>,
+}
+
+/// When a call started and ended, in nanoseconds since the Unix epoch, and how long it took
+/// in nanoseconds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Diff)]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub struct Timing {
+    pub start: u64,
+    pub end: u64,
+    /// Measured on the monotonic clock, so it may differ slightly from `end - start`.
+    pub duration: u64,
+}
+
+/// Times a call from `start` until `stop`.
+pub struct Timer {
+    start: SystemTime,
+    instant: Instant,
+}
+
+impl Timer {
+    pub fn start() -> Timer {
+        Timer {
+            start: SystemTime::now(),
+            instant: Instant::now(),
+        }
+    }
+
+    pub fn stop(self) -> Timing {
+        let duration = self.instant.elapsed();
+        Timing {
+            start: nanos_since_epoch(self.start),
+            end: nanos_since_epoch(SystemTime::now()),
+            duration: duration.as_nanos() as u64,
+        }
+    }
+}
+
+fn nanos_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Diff)]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub enum Outcome {
+    Ok,
+    /// The error chain, outermost context first.
+    Err(Vec<String>),
+}
+
+impl Outcome {
+    pub fn of<T>(result: &anyhow::Result<T>) -> Outcome {
+        match result {
+            Ok(_) => Outcome::Ok,
+            Err(e) => Outcome::Err(e.chain().map(ToString::to_string).collect()),
+        }
+    }
+}
+
+impl PartialEq for Transaction {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.params == other.params
+            && self.response == other.response
+            && self.outcome == other.outcome
+    }
 }
 
 impl Transaction {
+    /// Append this transaction to the store's latest run. Keys are a per-run sequence number
+    /// assigned inside the write transaction, so rows read back in exactly the order of the
+    /// writes.
     pub fn write(&self, db: &redb::Database) -> anyhow::Result<()> {
+        Self::write_all(db, std::slice::from_ref(self))
+    }
+
+    /// Append transactions to the store's latest run in order, in one write transaction.
+    pub fn write_all(db: &redb::Database, txs: &[Transaction]) -> anyhow::Result<()> {
         let write_txn = db.begin_write()?;
+        schema::prepare_write(&write_txn)?;
 
         {
-            let duration_since_epoch = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap();
-            let timestamp_nanos = duration_since_epoch.as_nanos();
-            let mut table = write_txn.open_table(TABLE)?;
+            let name = run::transactions_table(run::latest(&write_txn)?.as_deref());
+            let mut table = write_txn.open_table(TableDefinition::<u128, String>::new(&name))?;
 
-            let tx_s = serde_json::to_string(self)?;
+            let first = match table.last()? {
+                Some((last, _)) => last.value() + 1,
+                None => 0,
+            };
 
-            table.insert(timestamp_nanos, &tx_s)?;
+            for (key, tx) in (first..).zip(txs) {
+                let tx = tx.clone().stamped();
+                table.insert(key, &serde_json::to_string(&tx)?)?;
+            }
         }
 
         write_txn.commit()?;
 
         Ok(())
     }
+
+    /// Set the timestamp to now, unless it's already set.
+    pub fn stamped(self) -> Transaction {
+        Transaction {
+            timestamp: self
+                .timestamp
+                .or(Some(nanos_since_epoch(SystemTime::now()))),
+            ..self
+        }
+    }
+
+    /// Every transaction of the store's latest run.
+    pub fn read_all(db: &redb::Database) -> anyhow::Result<Vec<Transaction>> {
+        Self::read_run(db, None)
+    }
+
+    /// Every transaction of `run`, or of the latest run if `None`.
+    pub fn read_run(db: &redb::Database, run: Option<&str>) -> anyhow::Result<Vec<Transaction>> {
+        let run = run::resolve(db, run)?;
+        let read_txn = db.begin_read()?;
+        let name = run::transactions_table(run.as_deref());
+        let table = match read_txn.open_table(TableDefinition::<u128, String>::new(&name)) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut txs = Vec::new();
+        for tx in table.iter()? {
+            let tx = tx?.1.value();
+            txs.push(serde_json::from_str(&tx)?);
+        }
+
+        Ok(txs)
+    }
 }
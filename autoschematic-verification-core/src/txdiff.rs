@@ -0,0 +1,109 @@
+use serde::Serialize;
+
+use crate::{
+    crosscheck::CompareOptions,
+    expect::{CompiledTx, ExpectedOutcome, ParamMatcher},
+    param::Param,
+    tx::{Outcome, Transaction},
+};
+
+/// One way in which a transaction differs from its expectation.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "field", rename_all = "snake_case")]
+pub enum FieldDiff {
+    Kind {
+        expected: String,
+        actual: String,
+    },
+    ParamCount {
+        expected: usize,
+        actual: usize,
+    },
+    Param {
+        index: usize,
+        expected: ParamMatcher,
+        actual: Param,
+    },
+    MissingParam {
+        index: usize,
+        expected: ParamMatcher,
+    },
+    ExtraParam {
+        index: usize,
+        actual: Param,
+    },
+    Response {
+        expected: ParamMatcher,
+        actual: Option<String>,
+    },
+    Outcome {
+        expected: ExpectedOutcome,
+        actual: Option<Outcome>,
+    },
+}
+
+/// Compare `actual` to `expected` field by field.
+/// Params are compared by position, since they are the positional arguments of a connector call.
+pub fn field_diffs(
+    expected: &CompiledTx,
+    actual: &Transaction,
+    options: &CompareOptions,
+) -> Vec<FieldDiff> {
+    let mut out = Vec::new();
+    let left = expected.expected;
+
+    if left.kind != actual.kind {
+        out.push(FieldDiff::Kind {
+            expected: left.kind.clone(),
+            actual: actual.kind.clone(),
+        });
+    }
+
+    if left.params.len() != actual.params.len() {
+        out.push(FieldDiff::ParamCount {
+            expected: left.params.len(),
+            actual: actual.params.len(),
+        });
+    }
+
+    for i in 0..left.params.len().max(actual.params.len()) {
+        match (left.params.get(i), actual.params.get(i)) {
+            (Some(a), Some(b)) if !expected.param_matches(i, b, options) => {
+                out.push(FieldDiff::Param {
+                    index: i,
+                    expected: a.clone(),
+                    actual: b.clone(),
+                });
+            }
+            (Some(a), None) => out.push(FieldDiff::MissingParam {
+                index: i,
+                expected: a.clone(),
+            }),
+            (None, Some(b)) => out.push(FieldDiff::ExtraParam {
+                index: i,
+                actual: b.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    if let Some(response) = &left.response
+        && !expected.response_matches(actual.response.as_deref(), options)
+    {
+        out.push(FieldDiff::Response {
+            expected: response.clone(),
+            actual: actual.response.clone(),
+        });
+    }
+
+    if let Some(outcome) = &left.outcome
+        && !expected.outcome_matches(actual.outcome.as_ref(), options)
+    {
+        out.push(FieldDiff::Outcome {
+            expected: outcome.clone(),
+            actual: actual.outcome.clone(),
+        });
+    }
+
+    out
+}
@@ -0,0 +1,256 @@
+use std::{
+    sync::{
+        Arc, Mutex, Weak,
+        mpsc::{self, Receiver, Sender, SyncSender},
+    },
+    thread::JoinHandle,
+};
+
+use anyhow::{Context, anyhow};
+
+use crate::tx::Transaction;
+
+/// Most transactions committed in one write transaction.
+const MAX_BATCH: usize = 1024;
+
+/// Every writer still open in this process, for `close_all`.
+static WRITERS: Mutex<Vec<Weak<Inner>>> = Mutex::new(Vec::new());
+
+enum Message {
+    Tx(Transaction),
+    /// Reply once everything queued before has been committed.
+    Flush(SyncSender<Result<(), String>>),
+}
+
+/// Writes transactions to a store from a background thread, so that callers never block on
+/// redb. Whatever is queued when the thread wakes up is committed at once, in order.
+pub struct TxWriter {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    sender: Mutex<Option<Sender<Message>>>,
+    thread: Mutex<Option<JoinHandle<anyhow::Result<()>>>>,
+}
+
+impl TxWriter {
+    pub fn new(db: Arc<redb::Database>) -> anyhow::Result<TxWriter> {
+        let (sender, receiver) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name(String::from("tx-writer"))
+            .spawn(move || write_batches(&db, receiver))
+            .context("spawning transaction writer")?;
+
+        let inner = Arc::new(Inner {
+            sender: Mutex::new(Some(sender)),
+            thread: Mutex::new(Some(thread)),
+        });
+        WRITERS.lock().unwrap().push(Arc::downgrade(&inner));
+
+        Ok(TxWriter { inner })
+    }
+
+    /// Queue a transaction, stamping it now. Fails if the writer was closed or stopped on an
+    /// error; `close` returns that error.
+    pub fn send(&self, tx: Transaction) -> anyhow::Result<()> {
+        self.inner.send(Message::Tx(tx.stamped()))
+    }
+
+    /// Wait until every transaction queued so far is committed.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let (reply, done) = mpsc::sync_channel(1);
+        self.inner.send(Message::Flush(reply))?;
+        done.recv()
+            .context("transaction writer stopped")?
+            .map_err(|e| anyhow!(e))
+    }
+
+    /// Commit everything queued and stop the writer. Later sends fail.
+    pub fn close(&self) -> anyhow::Result<()> {
+        self.inner.close()
+    }
+}
+
+impl Inner {
+    fn send(&self, message: Message) -> anyhow::Result<()> {
+        match &*self.sender.lock().unwrap() {
+            Some(sender) => sender
+                .send(message)
+                .map_err(|_| anyhow!("transaction writer stopped")),
+            None => Err(anyhow!("transaction writer closed")),
+        }
+    }
+
+    fn close(&self) -> anyhow::Result<()> {
+        drop(self.sender.lock().unwrap().take());
+        match self.thread.lock().unwrap().take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| anyhow!("transaction writer panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            eprintln!("Closing transaction writer: {e:#}");
+        }
+    }
+}
+
+/// Close every writer in this process, committing everything queued. Connectors call this
+/// on shutdown.
+pub fn close_all() -> anyhow::Result<()> {
+    let writers: Vec<_> = WRITERS.lock().unwrap().drain(..).collect();
+    // A writer that fails doesn't keep the others from closing; the first error is returned.
+    let mut result = Ok(());
+    for writer in writers.iter().filter_map(Weak::upgrade) {
+        let closed = writer.close();
+        result = result.and(closed);
+    }
+    result
+}
+
+fn write_batches(db: &redb::Database, receiver: Receiver<Message>) -> anyhow::Result<()> {
+    while let Ok(first) = receiver.recv() {
+        let mut txs = Vec::new();
+        let mut flushes = Vec::new();
+
+        let mut next = Some(first);
+        while let Some(message) = next {
+            match message {
+                Message::Tx(tx) => txs.push(tx),
+                Message::Flush(reply) => flushes.push(reply),
+            }
+            next = if txs.len() < MAX_BATCH {
+                receiver.try_recv().ok()
+            } else {
+                None
+            };
+        }
+
+        let result = if txs.is_empty() {
+            Ok(())
+        } else {
+            Transaction::write_all(db, &txs)
+        };
+        for reply in flushes {
+            let _ = reply.send(result.as_ref().map_err(|e| format!("{e:#}")).copied());
+        }
+        result?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{TempDir, tx, write_unversioned};
+
+    /// `close_all` closes every writer in the process, so tests that open writers take turns.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    fn serial() -> std::sync::MutexGuard<'static, ()> {
+        SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn params(db: &redb::Database) -> Vec<String> {
+        Transaction::read_run(db, None)
+            .unwrap()
+            .iter()
+            .map(|tx| tx.params[0].as_text().into_owned())
+            .collect()
+    }
+
+    /// A writer to a store that refuses writes, having been written before schema versions.
+    fn failing_writer(dir: &TempDir) -> TxWriter {
+        let db = dir.db("old.redb");
+        write_unversioned(&db, &[r#"{"kind":"get","params":["a"]}"#]);
+        TxWriter::new(Arc::new(db)).unwrap()
+    }
+
+    #[test]
+    fn order_is_kept_across_batches() {
+        let _serial = serial();
+        let dir = TempDir::new();
+        let db = Arc::new(dir.db("store.redb"));
+        let writer = TxWriter::new(db.clone()).unwrap();
+
+        let expected: Vec<String> = (0..MAX_BATCH * 2 + 10).map(|i| i.to_string()).collect();
+        for param in &expected {
+            writer.send(tx("get", param)).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert_eq!(params(&db), expected);
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn flush_commits_what_was_sent() {
+        let _serial = serial();
+        let dir = TempDir::new();
+        let db = Arc::new(dir.db("store.redb"));
+        let writer = TxWriter::new(db.clone()).unwrap();
+
+        writer.send(tx("get", "a")).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(params(&db), ["a"]);
+
+        let txs = Transaction::read_run(&db, None).unwrap();
+        assert!(txs[0].timestamp.is_some());
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn sends_after_close_fail() {
+        let _serial = serial();
+        let dir = TempDir::new();
+        let db = Arc::new(dir.db("store.redb"));
+        let writer = TxWriter::new(db.clone()).unwrap();
+
+        writer.send(tx("get", "a")).unwrap();
+        writer.close().unwrap();
+        assert_eq!(params(&db), ["a"]);
+
+        let e = writer.send(tx("get", "b")).unwrap_err();
+        assert!(e.to_string().contains("closed"), "{e}");
+        assert!(writer.flush().is_err());
+    }
+
+    #[test]
+    fn write_errors_reach_flush_and_close() {
+        let _serial = serial();
+        let dir = TempDir::new();
+        let writer = failing_writer(&dir);
+
+        writer.send(tx("get", "b")).unwrap();
+        let e = format!("{:#}", writer.flush().unwrap_err());
+        assert!(e.contains("schema version 1"), "{e}");
+
+        // The thread stopped on the error, which `close` reports.
+        let e = format!("{:#}", writer.close().unwrap_err());
+        assert!(e.contains("schema version 1"), "{e}");
+    }
+
+    #[test]
+    fn close_all_closes_every_writer_despite_errors() {
+        let _serial = serial();
+        let dir = TempDir::new();
+        let failing = failing_writer(&dir);
+        let db = Arc::new(dir.db("store.redb"));
+        let writer = TxWriter::new(db.clone()).unwrap();
+
+        failing.send(tx("get", "a")).unwrap();
+        writer.send(tx("get", "b")).unwrap();
+        let e = format!("{:#}", close_all().unwrap_err());
+        assert!(e.contains("schema version 1"), "{e}");
+
+        // The writer registered after the failing one was still flushed and closed.
+        assert_eq!(params(&db), ["b"]);
+        assert!(writer.send(tx("get", "c")).is_err());
+    }
+}
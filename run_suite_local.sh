@@ -1,7 +1,6 @@
 #!/bin/bash
 set -exo pipefail
 
-export AUTOSCHEMATIC_NO_SANDBOX=true
 cargo run --bin autoschematic-testbench -- run --sequence sequences/unbundle.ron
 cargo run --bin autoschematic-testbench -- run --sequence sequences/import.ron
 cargo run --bin autoschematic-testbench -- run --sequence sequences/plan.ron